@@ -0,0 +1,213 @@
+//! Frames built up from layers, each drawn over the ones before it, so what goes on top of the
+//! glass (a clock, a tint, a flash) doesn't have to work its way around the escape sequences and
+//! wide characters already in the text.
+
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+use crate::output::RESET_FOREGROUND;
+
+
+const REVERSE: &str = "\x1b[7m";
+const NO_REVERSE: &str = "\x1b[27m";
+
+
+/// One column of a frame.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Cell {
+    /// Empty for the second column of a wide character
+    pub glyph: String,
+    /// The escape sequence setting its color, or the default color if None
+    pub color: Option<String>,
+    /// With the foreground and background swapped
+    pub reverse: bool
+}
+
+impl Cell {
+
+    fn blank() -> Cell {
+        Cell { glyph: " ".to_string(), color: None, reverse: false }
+    }
+
+    fn glyph(glyph: String) -> Cell {
+        Cell { glyph, color: None, reverse: false }
+    }
+
+}
+
+
+/// Something drawn onto a frame.
+pub enum Layer<'a> {
+    /// Rows of text with escape sequences in them, like the glass and its sand, covering everything
+    /// under them from the top left corner
+    Rows(&'a [String]),
+    /// Plain text with its top row at `top` and centered on column `center`, where what's under it
+    /// shows through its spaces. The frame is widened where it doesn't fit, but it doesn't get any
+    /// taller.
+    Stencil { text: &'a [String], top: usize, center: usize },
+    /// Gives a color to everything that's still in the default one
+    Tint(&'a str),
+    /// Swaps the foreground and background of everything
+    Reverse
+}
+
+
+/// A grid of cells, with all the rows as wide.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Frame {
+    rows: Vec<Vec<Cell>>
+}
+
+impl Frame {
+
+    /// Draws `layers` onto an empty frame, the first one at the bottom.
+    pub fn compose(layers: &[Layer]) -> Frame {
+        let mut frame = Frame::default();
+        for layer in layers {
+            frame.draw(layer);
+        }
+        frame
+    }
+
+    fn width(&self) -> usize {
+        self.rows.first().map_or(0, Vec::len)
+    }
+
+    fn draw(&mut self, layer: &Layer) {
+        match *layer {
+            Layer::Rows(rows) => {
+                if self.rows.len() < rows.len() {
+                    let width = self.width();
+                    self.rows.resize(rows.len(), vec![Cell::blank(); width]);
+                }
+                for (y, row) in rows.iter().enumerate() {
+                    // The rest of a wide character comes along with it
+                    for (x, cell) in parse_row(row).into_iter().enumerate().filter(|(_, cell)| !cell.glyph.is_empty()) {
+                        self.put(x, y, cell);
+                    }
+                }
+            },
+            Layer::Stencil { text, top, center } => {
+                let text_width = text.iter().map(|row| row.chars().map(|ch| ch.width().unwrap_or(0)).sum()).max().unwrap_or(0);
+                let pad_left = (text_width / 2).saturating_sub(center);
+                let left = center + pad_left - text_width / 2;
+                for row in &mut self.rows {
+                    row.splice(0..0, std::iter::repeat_n(Cell::blank(), pad_left));
+                }
+                self.widen(left + text_width);
+
+                for (y, text_row) in text.iter().enumerate().take(self.rows.len().saturating_sub(top)) {
+                    let mut x = left;
+                    for ch in text_row.chars() {
+                        if ch != ' ' {
+                            self.put(x, top + y, Cell::glyph(ch.to_string()));
+                        }
+                        x += ch.width().unwrap_or(0);
+                    }
+                }
+            },
+            Layer::Tint(color) => for cell in self.rows.iter_mut().flatten() {
+                if cell.color.is_none() {
+                    cell.color = Some(color.to_string());
+                }
+            },
+            Layer::Reverse => for cell in self.rows.iter_mut().flatten() {
+                cell.reverse = true;
+            }
+        }
+    }
+
+    /// Makes every row at least `width` cells wide.
+    fn widen(&mut self, width: usize) {
+        let width = self.width().max(width);
+        for row in &mut self.rows {
+            row.resize(width, Cell::blank());
+        }
+    }
+
+    /// Puts `cell` at `x` of row `y`, along with the empty cells after it if it's wide. A wide
+    /// character can't be half covered, so what's left of one becomes a space.
+    fn put(&mut self, x: usize, y: usize, cell: Cell) {
+        let width = cell.glyph.width().max(1);
+        self.widen(x + width);
+        let row = &mut self.rows[y];
+
+        if row[x].glyph.is_empty() && x > 0 {
+            row[x - 1].glyph = " ".to_string();
+        }
+        if row.get(x + width).is_some_and(|after| after.glyph.is_empty()) {
+            row[x + width].glyph = " ".to_string();
+        }
+        for after in &mut row[x + 1..x + width] {
+            *after = Cell { glyph: String::new(), ..cell.clone() };
+        }
+        row[x] = cell;
+    }
+
+    /// The frame as text again, with an escape sequence wherever the color changes.
+    pub fn to_lines(&self) -> Vec<String> {
+        self.rows.iter().map(|row| {
+            let mut line = String::new();
+            let mut color: Option<&str> = None;
+            let mut reverse = false;
+            for cell in row.iter().filter(|cell| !cell.glyph.is_empty()) {
+                if cell.color.as_deref() != color {
+                    color = cell.color.as_deref();
+                    line += color.unwrap_or(RESET_FOREGROUND);
+                }
+                if cell.reverse != reverse {
+                    reverse = cell.reverse;
+                    line += if reverse { REVERSE } else { NO_REVERSE };
+                }
+                line += &cell.glyph;
+            }
+
+            if color.is_some() {
+                line += RESET_FOREGROUND;
+            }
+            if reverse {
+                line += NO_REVERSE;
+            }
+            line
+        }).collect()
+    }
+
+}
+
+/// Splits a line of text into cells, keeping track of the colors its escape sequences set.
+fn parse_row(line: &str) -> Vec<Cell> {
+    let mut cells = Vec::<Cell>::new();
+    let mut color: Option<String> = None;
+    let mut reverse = false;
+    let mut escape: Option<String> = None;
+    // Where the last character went, for the ones that combine with it
+    let mut last_glyph: Option<usize> = None;
+    for ch in line.chars() {
+        if let Some(sequence) = &mut escape {
+            sequence.push(ch);
+            if ch.is_ascii_alphabetic() {
+                match sequence.as_str() {
+                    RESET_FOREGROUND | "\x1b[0m" | "\x1b[m" => color = None,
+                    REVERSE => reverse = true,
+                    NO_REVERSE => reverse = false,
+                    _ => color = Some(sequence.clone())
+                }
+                escape = None;
+            }
+        } else if ch == '\x1b' {
+            escape = Some(ch.to_string());
+        } else {
+            match (ch.width().unwrap_or(0), last_glyph) {
+                (0, Some(last)) => cells[last].glyph.push(ch),
+                (width, _) => {
+                    let cell = Cell { glyph: ch.to_string(), color: color.clone(), reverse };
+                    let rest = Cell { glyph: String::new(), ..cell.clone() };
+                    last_glyph = Some(cells.len());
+                    cells.push(cell);
+                    cells.extend(std::iter::repeat_n(rest, width.saturating_sub(1)));
+                }
+            }
+        }
+    }
+
+    cells
+}
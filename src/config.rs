@@ -0,0 +1,142 @@
+use std::path::PathBuf;
+
+
+/// Presets available without a config file. The config file can add more under `[presets]`, or
+/// replace these by using the same name.
+const BUILTIN_PRESETS: &str = r#"
+[pomodoro]
+length = "25m"
+label = "pomodoro"
+bell = true
+
+[break]
+length = "5m"
+label = "break"
+bell = true
+
+[tea]
+length = "3m"
+label = "tea"
+width = 5
+height = 10
+bell = true
+
+[egg]
+length = "7m"
+label = "egg"
+bell = true
+"#;
+
+
+/// Where the config file is looked for: `$XDG_CONFIG_HOME/hourgals/config.toml`, falling back to
+/// `~/.config` (or `%APPDATA%` on Windows) when that's not set.
+pub fn path() -> Option<PathBuf> {
+    let config_dir = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => if cfg!(windows) {
+            PathBuf::from(std::env::var_os("APPDATA")?)
+        } else {
+            PathBuf::from(std::env::var_os("HOME")?).join(".config")
+        }
+    };
+
+    Some(config_dir.join("hourgals").join("config.toml"))
+}
+
+/// Reads the config file. A missing file is the same as an empty one.
+pub fn load() -> Result<toml::Table, Box<dyn std::error::Error>> {
+    let Some(path) = path() else { return Ok(toml::Table::new()) };
+
+    match std::fs::read_to_string(&path) {
+        Ok(text) => text.parse::<toml::Table>().map_err(|err| format!("{}: {}", path.display(), err).into()),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(toml::Table::new()),
+        Err(err) => Err(format!("{}: {}", path.display(), err).into())
+    }
+}
+
+/// Every preset, built in or from the `[presets]` section of `config`.
+pub fn presets(config: &toml::Table) -> Result<toml::Table, String> {
+    let mut presets = BUILTIN_PRESETS.parse::<toml::Table>().expect("built in presets should be valid");
+
+    match config.get("presets") {
+        Some(toml::Value::Table(custom)) => presets.extend(custom.clone()),
+        Some(_) => return Err("`presets` in config file must be a table".to_string()),
+        None => ()
+    }
+
+    Ok(presets)
+}
+
+/// The option defaults from `config`, with those of the named preset taking priority.
+pub fn settings(config: &toml::Table, preset: Option<&str>) -> Result<toml::Table, String> {
+    let mut settings = config.clone();
+    settings.remove("presets");
+
+    if let Some(name) = preset {
+        match presets(config)?.remove(name) {
+            Some(toml::Value::Table(preset)) => settings.extend(preset),
+            Some(_) => return Err(format!("preset `{}` must be a table", name)),
+            None => return Err(format!("unknown preset `{}` (see `hourgals presets`)", name))
+        }
+    }
+
+    Ok(settings)
+}
+
+/// Makes the values in `config` the defaults of the options with the same long name, in `command`
+/// and its subcommands, so the command line still overrides them.
+///
+/// ```toml
+/// width = 11
+/// bell = true
+/// bell-at = ["1m", "10s"]
+/// ```
+pub fn apply_defaults(mut command: clap::Command, config: &toml::Table) -> Result<clap::Command, String> {
+    for (key, value) in config {
+        let values: Vec<String> = match value {
+            toml::Value::Array(items) => items.iter().map(value_to_string).collect::<Result<_, _>>(),
+            _ => value_to_string(value).map(|value| vec![value])
+        }.map_err(|err| format!("option `{}` in config file: {}", key, err))?;
+
+        let found;
+        (command, found) = mut_arg_everywhere(command, key, &|arg| arg.default_values(&values));
+        if !found {
+            return Err(format!("unknown option `{}` in config file", key));
+        }
+    }
+
+    Ok(command)
+}
+
+/// Changes the option with the long name `key` in `command` and every subcommand that has it.
+/// Also returns whether any of them did.
+pub fn mut_arg_everywhere(mut command: clap::Command, key: &str, change: &impl Fn(clap::Arg) -> clap::Arg) -> (clap::Command, bool) {
+    let id = command.get_arguments()
+        .find(|arg| arg.get_long() == Some(key))
+        .map(|arg| arg.get_id().clone());
+    let mut found = id.is_some();
+    if let Some(id) = id {
+        command = command.mut_arg(id, change);
+    }
+
+    let subcommands: Vec<String> = command.get_subcommands().map(|subcommand| subcommand.get_name().to_string()).collect();
+    for name in subcommands {
+        command = command.mut_subcommand(name, |subcommand| {
+            let (subcommand, found_here) = mut_arg_everywhere(subcommand, key, change);
+            found |= found_here;
+            subcommand
+        });
+    }
+
+    (command, found)
+}
+
+fn value_to_string(value: &toml::Value) -> Result<String, &'static str> {
+    match value {
+        toml::Value::String(text) => Ok(text.clone()),
+        toml::Value::Integer(number) => Ok(number.to_string()),
+        toml::Value::Float(number) => Ok(number.to_string()),
+        toml::Value::Boolean(flag) => Ok(flag.to_string()),
+        _ => Err("must be a string, number, boolean, or array of those")
+    }
+}
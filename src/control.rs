@@ -0,0 +1,103 @@
+use chrono::TimeDelta;
+
+use crate::timer::parse_time;
+
+
+/// Something to do to a running timer, asked for from outside of it.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Command {
+    Pause,
+    Resume,
+    TogglePause,
+    Flip,
+    AddTime(TimeDelta),
+    /// Give this much more time from now, with the sand refilled to match, even if the time is up.
+    Snooze(TimeDelta),
+    /// Reply with the progress of the timer.
+    Status,
+    Quit { exit_code: u8 }
+}
+
+impl std::str::FromStr for Command {
+    type Err = String;
+
+    /// Reads commands like `pause` or `add 5m`.
+    fn from_str(line: &str) -> Result<Command, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+
+        match words.as_slice() {
+            ["pause"] => Ok(Command::Pause),
+            ["resume"] => Ok(Command::Resume),
+            ["toggle"] => Ok(Command::TogglePause),
+            ["flip"] => Ok(Command::Flip),
+            ["add", time @ ..] if !time.is_empty() => match parse_time(&time.join(" ")) {
+                Ok(time) => Ok(Command::AddTime(time)),
+                Err(err) => Err(format!("invalid time `{}`: {}", time.join(" "), err))
+            },
+            ["snooze", time @ ..] if !time.is_empty() => match parse_time(&time.join(" ")) {
+                Ok(time) => Ok(Command::Snooze(time)),
+                Err(err) => Err(format!("invalid time `{}`: {}", time.join(" "), err))
+            },
+            ["status"] => Ok(Command::Status),
+            ["quit"] => Ok(Command::Quit { exit_code: 0 }),
+            [] => Err("empty command".to_string()),
+            _ => Err(format!("unknown command `{}` (valid commands are pause, resume, toggle, flip, add TIME, snooze TIME, status, and quit)", line.trim()))
+        }
+    }
+}
+
+
+/// A command, and where to send the answer to it if someone is waiting for one.
+pub struct Request {
+    pub command: Command,
+    pub reply: Option<std::sync::mpsc::Sender<String>>
+}
+
+impl Request {
+
+    pub fn respond(&self, answer: String) {
+        if let Some(reply) = &self.reply {
+            // The asker may have hung up already, that's fine
+            let _ = reply.send(answer);
+        }
+    }
+
+}
+
+
+/// Reads commands from stdin line by line in the background, until it's closed. Lines that aren't
+/// commands are reported on stderr, since stdout has the frames on it.
+pub fn read_stdin_commands() -> std::sync::mpsc::Receiver<Request> {
+    let (sender, requests) = std::sync::mpsc::channel();
+
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lines() {
+            let Ok(line) = line else { break };
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            match line.parse::<Command>() {
+                Ok(command) => if sender.send(Request { command, reply: None }).is_err() {
+                    break;
+                },
+                Err(err) => eprintln!("error: {}", err)
+            }
+        }
+    });
+
+    requests
+}
+
+/// Sends a single resume once Enter is pressed, for starting a paused timer from the terminal.
+pub fn resume_on_enter() -> std::sync::mpsc::Receiver<Request> {
+    let (sender, requests) = std::sync::mpsc::channel();
+
+    std::thread::spawn(move || {
+        if std::io::stdin().read_line(&mut String::new()).is_ok_and(|len| len > 0) {
+            let _ = sender.send(Request { command: Command::Resume, reply: None });
+        }
+    });
+
+    requests
+}
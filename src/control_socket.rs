@@ -0,0 +1,87 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Receiver, Sender};
+
+use crate::control::{Command, Request};
+
+
+/// Listens for commands on a Unix domain socket, one per line. Every command gets a one line answer,
+/// which starts with `error:` if it couldn't be carried out.
+pub struct ControlSocket {
+    path: PathBuf,
+    requests: Receiver<Request>
+}
+
+impl ControlSocket {
+
+    pub fn bind(path: &Path) -> std::io::Result<ControlSocket> {
+        // A socket file nobody is listening on is left over from an instance that didn't clean up
+        if path.exists() && UnixStream::connect(path).is_err() {
+            std::fs::remove_file(path)?;
+        }
+
+        let listener = UnixListener::bind(path)?;
+        let (sender, requests) = std::sync::mpsc::channel();
+
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let sender = sender.clone();
+                std::thread::spawn(move || serve(stream, sender));
+            }
+        });
+
+        Ok(ControlSocket {
+            path: path.to_owned(),
+            requests
+        })
+    }
+
+    /// Requests received since the last call, without blocking.
+    pub fn pending(&self) -> impl Iterator<Item = Request> + '_ {
+        self.requests.try_iter()
+    }
+
+}
+
+impl Drop for ControlSocket {
+
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+
+}
+
+
+fn serve(stream: UnixStream, sender: Sender<Request>) -> std::io::Result<()> {
+    let mut writer = stream.try_clone()?;
+
+    for line in BufReader::new(stream).lines() {
+        let answer = match line?.parse::<Command>() {
+            Ok(command) => {
+                let (reply, answer) = std::sync::mpsc::channel();
+                if sender.send(Request { command, reply: Some(reply) }).is_err() {
+                    break;
+                }
+
+                answer.recv().unwrap_or_else(|_| "error: timer is shutting down".to_string())
+            },
+            Err(err) => format!("error: {}", err)
+        };
+
+        writeln!(writer, "{}", answer)?;
+    }
+
+    Ok(())
+}
+
+/// Sends one command to the instance listening on `path` and returns its answer.
+pub fn send(path: &Path, command: &str) -> std::io::Result<String> {
+    let mut stream = UnixStream::connect(path)?;
+    writeln!(stream, "{}", command)?;
+    stream.shutdown(std::net::Shutdown::Write)?;
+
+    let mut answer = String::new();
+    stream.read_to_string(&mut answer)?;
+    Ok(answer.trim_end().to_string())
+}
@@ -0,0 +1,115 @@
+use std::sync::mpsc::{Receiver, Sender};
+use std::sync::{Arc, Mutex};
+
+use chrono::TimeDelta;
+
+use crate::control::{Command, Request};
+use crate::output::Progress;
+
+
+/// Serves an `org.hourgals.Timer` object on the session bus, at `/org/hourgals/Timer` of the name
+/// `org.hourgals.Timer.instance<pid>` so several timers can run at once. The properties are about
+/// the first timer, and the methods do the same as the commands of the control socket.
+pub struct DbusService {
+    _connection: zbus::blocking::Connection,
+    state: Arc<Mutex<TimerState>>,
+    requests: Receiver<Request>
+}
+
+impl DbusService {
+
+    pub fn connect() -> zbus::Result<DbusService> {
+        let state = Arc::new(Mutex::new(TimerState::default()));
+        let (sender, requests) = std::sync::mpsc::channel();
+
+        let interface = TimerInterface { state: Arc::clone(&state), requests: sender };
+        let connection = zbus::blocking::connection::Builder::session()?
+            .name(format!("org.hourgals.Timer.instance{}", std::process::id()))?
+            .serve_at("/org/hourgals/Timer", interface)?
+            .build()?;
+
+        Ok(DbusService { _connection: connection, state, requests })
+    }
+
+    /// Requests made since the last call, without blocking.
+    pub fn pending(&self) -> impl Iterator<Item = Request> + '_ {
+        self.requests.try_iter()
+    }
+
+    pub fn update(&self, progress: &Progress) {
+        *self.state.lock().unwrap() = TimerState {
+            remaining_secs: progress.remaining_secs.unwrap_or(0.0),
+            progress: progress.time_progress,
+            label: progress.label.clone().unwrap_or_default()
+        };
+    }
+
+}
+
+
+/// What the properties say, as of the last frame.
+#[derive(Default)]
+struct TimerState {
+    remaining_secs: f64,
+    progress: f64,
+    label: String
+}
+
+struct TimerInterface {
+    state: Arc<Mutex<TimerState>>,
+    requests: Sender<Request>
+}
+
+impl TimerInterface {
+
+    fn send(&self, command: Command) {
+        let _ = self.requests.send(Request { command, reply: None });
+    }
+
+}
+
+// The properties change every frame, so they're read when needed instead of signaled
+#[zbus::interface(name = "org.hourgals.Timer")]
+impl TimerInterface {
+
+    fn pause(&self) {
+        self.send(Command::Pause);
+    }
+
+    fn resume(&self) {
+        self.send(Command::Resume);
+    }
+
+    fn flip(&self) {
+        self.send(Command::Flip);
+    }
+
+    /// Negative seconds take time away.
+    fn add_time(&self, seconds: f64) -> zbus::fdo::Result<()> {
+        if !seconds.is_finite() {
+            return Err(zbus::fdo::Error::InvalidArgs(format!("{} isn't a number of seconds", seconds)));
+        }
+
+        self.send(Command::AddTime(TimeDelta::milliseconds((seconds * 1000.0) as i64)));
+        Ok(())
+    }
+
+    /// 0 when counting up.
+    #[zbus(property(emits_changed_signal = "false"))]
+    fn remaining_seconds(&self) -> f64 {
+        self.state.lock().unwrap().remaining_secs
+    }
+
+    /// How much of the time has passed, from 0 to 1.
+    #[zbus(property(emits_changed_signal = "false"))]
+    fn progress(&self) -> f64 {
+        self.state.lock().unwrap().progress
+    }
+
+    /// Empty when the timer has none.
+    #[zbus(property(emits_changed_signal = "false"))]
+    fn label(&self) -> String {
+        self.state.lock().unwrap().label.clone()
+    }
+
+}
@@ -0,0 +1,75 @@
+use std::io::Write;
+use std::path::Path;
+
+use chrono::{DateTime, TimeDelta, Utc};
+
+
+/// Something that happened to a timer, as it goes in the log.
+#[derive(serde::Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum LogEvent {
+    /// A phase started, or started over
+    Started { duration_secs: f64 },
+    Paused,
+    Resumed,
+    Flipped,
+    TimeAdded { added_secs: f64 },
+    /// The remaining time dropped to a `--bell-at` threshold.
+    Warned { remaining_secs: f64 },
+    Completed
+}
+
+impl LogEvent {
+
+    pub fn started(duration: TimeDelta) -> LogEvent {
+        LogEvent::Started { duration_secs: secs(duration) }
+    }
+
+    pub fn time_added(added: TimeDelta) -> LogEvent {
+        LogEvent::TimeAdded { added_secs: secs(added) }
+    }
+
+    pub fn warned(remaining: TimeDelta) -> LogEvent {
+        LogEvent::Warned { remaining_secs: secs(remaining) }
+    }
+
+}
+
+fn secs(time: TimeDelta) -> f64 {
+    time.num_milliseconds() as f64 / 1000.0
+}
+
+
+/// One line of the log.
+#[derive(serde::Serialize)]
+struct LogLine<'a> {
+    at: DateTime<Utc>,
+    label: Option<&'a str>,
+    #[serde(flatten)]
+    event: LogEvent
+}
+
+
+/// Appends a line of JSON to a file for every `LogEvent`, to see afterwards how the timers got used.
+pub struct EventLog {
+    file: std::fs::File
+}
+
+impl EventLog {
+
+    /// Opens `path` for appending, creating it if needed, so one log can be kept across runs.
+    pub fn open(path: &Path) -> std::io::Result<EventLog> {
+        let file = std::fs::OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(EventLog { file })
+    }
+
+    /// Writes `event`, which happened just now to the timer labeled `label`.
+    pub fn write(&mut self, event: LogEvent, label: Option<&str>) -> std::io::Result<()> {
+        let line = LogLine { at: Utc::now(), label, event };
+        // In one write, so lines from timers logging to the same file don't get mixed up
+        let mut text = serde_json::to_string(&line)?;
+        text.push('\n');
+        self.file.write_all(text.as_bytes())
+    }
+
+}
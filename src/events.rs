@@ -0,0 +1,300 @@
+use std::io::Write;
+
+use chrono::TimeDelta;
+
+#[cfg(feature = "notify")]
+use crate::control::{Command, Request};
+use crate::event_log::{EventLog, LogEvent};
+use crate::timer::Phase;
+
+
+/// Something that happened to the timer, which the user may want to react to.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TimerEvent {
+    /// The time ran out. Fires once per round.
+    Completed,
+    /// The glass was turned over, to start the next round or by request.
+    Flipped,
+    /// The remaining time dropped to one of the `--bell-at` thresholds.
+    Warning { remaining: TimeDelta },
+    /// The time got to the point of the `--at` hook with this index.
+    Hook { index: usize }
+}
+
+
+/// Fires a warning once per round when the remaining time drops to each threshold.
+pub struct WarningSchedule {
+    /// Longest first
+    thresholds: Vec<TimeDelta>,
+    next: usize
+}
+
+impl WarningSchedule {
+
+    pub fn new(mut thresholds: Vec<TimeDelta>, duration: TimeDelta) -> WarningSchedule {
+        thresholds.sort_by(|a, b| b.cmp(a));
+        let mut schedule = WarningSchedule { thresholds, next: 0 };
+        schedule.reset(duration);
+        schedule
+    }
+
+    /// Starts over with `remaining` time left. Thresholds that aren't shorter than that don't fire.
+    pub fn reset(&mut self, remaining: TimeDelta) {
+        self.next = self.thresholds.iter().take_while(|&&threshold| threshold >= remaining).count();
+    }
+
+    pub fn poll(&mut self, remaining: TimeDelta, events: &mut Vec<TimerEvent>) {
+        while let Some(&threshold) = self.thresholds.get(self.next) && remaining <= threshold {
+            self.next += 1;
+            events.push(TimerEvent::Warning { remaining: threshold });
+        }
+    }
+
+}
+
+
+/// A point of each round where an `--at` command runs.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum HookPoint {
+    /// When this much of the time has passed, from 0 to 1
+    Progress(f64),
+    /// When this much time is left
+    Remaining(TimeDelta)
+}
+
+impl HookPoint {
+
+    /// How much time is left at this point of a round of `duration`.
+    pub fn remaining(self, duration: TimeDelta) -> TimeDelta {
+        match self {
+            HookPoint::Progress(progress) => TimeDelta::milliseconds((duration.num_milliseconds() as f64 * (1.0 - progress)) as i64),
+            HookPoint::Remaining(remaining) => remaining
+        }
+    }
+
+}
+
+/// Reads a percentage of the time that's passed like `50%`, or a time that's left like `5m`.
+pub fn parse_hook_point(text: &str) -> Result<HookPoint, String> {
+    match text.trim().strip_suffix('%') {
+        Some(percent) => match percent.trim().parse::<f64>() {
+            Ok(percent) if (0.0..=100.0).contains(&percent) => Ok(HookPoint::Progress(percent / 100.0)),
+            _ => Err(format!("invalid percentage `{}`, it has to be from 0% to 100%", text))
+        },
+        None => Ok(HookPoint::Remaining(crate::timer::parse_time(text).map_err(|err| format!("invalid time `{}`: {}", text, err))?))
+    }
+}
+
+/// Reads an `--at` hook like `50%=notify-send halfway` or `5m=echo 5 minutes left`.
+pub fn parse_hook(text: &str) -> Result<(HookPoint, String), String> {
+    let Some((point, command)) = text.split_once('=') else {
+        return Err(format!("`{}` has no command, it should be like 50%=COMMAND or 5m=COMMAND", text));
+    };
+
+    Ok((parse_hook_point(point)?, command.to_string()))
+}
+
+/// Fires each hook once per round when the remaining time drops to its point, like
+/// `WarningSchedule` does.
+pub struct HookSchedule {
+    points: Vec<HookPoint>,
+    /// How much time is left at each point in this round, longest first, with the index of the point
+    due: Vec<(TimeDelta, usize)>,
+    next: usize
+}
+
+impl HookSchedule {
+
+    pub fn new(points: Vec<HookPoint>, duration: TimeDelta) -> HookSchedule {
+        let mut schedule = HookSchedule { points, due: Vec::new(), next: 0 };
+        schedule.reset(duration, duration);
+        schedule
+    }
+
+    /// Starts over with `remaining` time left of a round of `duration`. Points that aren't after
+    /// that don't fire.
+    pub fn reset(&mut self, remaining: TimeDelta, duration: TimeDelta) {
+        self.due = self.points.iter().enumerate().map(|(index, point)| (point.remaining(duration), index)).collect();
+        self.due.sort_by(|a, b| b.cmp(a));
+        self.next = self.due.iter().take_while(|&&(due, _)| due >= remaining).count();
+    }
+
+    pub fn poll(&mut self, remaining: TimeDelta, events: &mut Vec<TimerEvent>) {
+        while let Some(&(due, index)) = self.due.get(self.next) && remaining <= due {
+            self.next += 1;
+            events.push(TimerEvent::Hook { index });
+        }
+    }
+
+}
+
+
+/// How to ring the terminal bell when the time is up.
+pub struct BellPattern {
+    pub count: u32,
+    pub interval: std::time::Duration
+}
+
+
+/// What to do when events fire.
+pub struct EventActions {
+    #[cfg(feature = "notify")]
+    pub notify: bool,
+    /// Where the actions picked on notifications go, to be carried out like the other requests
+    #[cfg(feature = "notify")]
+    pub notification_requests: std::sync::mpsc::Sender<Request>,
+    pub bell: Option<BellPattern>,
+    /// Ring the bell once on warnings.
+    pub bell_on_warning: bool,
+    pub exec: Option<String>,
+    pub exec_on_flip: Option<String>,
+    /// The commands of the `--at` hooks
+    pub hooks: Vec<String>,
+    /// Where `--log` writes the events
+    pub log: Option<EventLog>,
+    /// Bells still ringing in the background
+    pub ringing: Vec<std::thread::JoinHandle<()>>
+}
+
+impl EventActions {
+
+    /// Reacts to `event`, which happened during `phase`.
+    pub fn handle(&mut self, event: TimerEvent, phase: &Phase) -> Result<(), Box<dyn std::error::Error>> {
+        #[cfg(feature = "notify")]
+        if self.notify && event == TimerEvent::Completed {
+            // Without a notification server there's still the bell and `--exec` to let it be known
+            if let Err(err) = show_notification(phase.label.as_deref(), phase.duration, self.notification_requests.clone()) {
+                eprintln!("can't show a notification: {}", err);
+            }
+        }
+
+        let log_event = match event {
+            TimerEvent::Completed => Some(LogEvent::Completed),
+            TimerEvent::Flipped => Some(LogEvent::Flipped),
+            TimerEvent::Warning { remaining } => Some(LogEvent::warned(remaining)),
+            TimerEvent::Hook { .. } => None
+        };
+        if let Some(log_event) = log_event {
+            self.log(log_event, phase)?;
+        }
+
+        self.ringing.retain(|handle| !handle.is_finished());
+
+        match event {
+            TimerEvent::Completed => if let Some(bell) = &self.bell {
+                self.ringing.push(ring_bell(bell.count, bell.interval));
+            },
+            TimerEvent::Warning { .. } => if self.bell_on_warning {
+                self.ringing.push(ring_bell(1, std::time::Duration::ZERO));
+            },
+            TimerEvent::Flipped | TimerEvent::Hook { .. } => ()
+        }
+
+        let command = match event {
+            TimerEvent::Completed => self.exec.as_ref(),
+            TimerEvent::Flipped => self.exec_on_flip.as_ref(),
+            TimerEvent::Warning { .. } => None,
+            TimerEvent::Hook { index } => self.hooks.get(index)
+        };
+
+        if let Some(command) = command {
+            spawn_shell(command, phase.label.as_deref())?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes `event` to the `--log`, if there is one.
+    pub fn log(&mut self, event: LogEvent, phase: &Phase) -> std::io::Result<()> {
+        match &mut self.log {
+            Some(log) => log.write(event, phase.label.as_deref()),
+            None => Ok(())
+        }
+    }
+
+    /// Waits for the actions that shouldn't be cut short by exiting.
+    pub fn finish(self) {
+        for handle in self.ringing {
+            let _ = handle.join();
+        }
+    }
+
+}
+
+
+/// How much more time the snooze action of the notification gives.
+#[cfg(feature = "notify")]
+const SNOOZE_TIME: TimeDelta = TimeDelta::minutes(5);
+
+/// Shows that the time is up, with actions to snooze or dismiss it where notifications can have
+/// them. Picking snooze sends a request to `requests`.
+#[cfg(feature = "notify")]
+fn show_notification(label: Option<&str>, duration: TimeDelta, requests: std::sync::mpsc::Sender<Request>) -> Result<(), notify_rust::error::Error> {
+    let mut notification = notify_rust::Notification::new();
+    notification
+        .appname("hourgals")
+        .summary(label.unwrap_or("Time is up"))
+        .body(&format!("{} timer finished", crate::timer::format_time(duration)));
+
+    // Waiting for the action on macOS needs the main thread's run loop, which we don't have
+    #[cfg(not(target_os = "macos"))]
+    {
+        let handle = notification
+            .action("snooze", &format!("Snooze {}", crate::timer::format_time(SNOOZE_TIME)))
+            .action("dismiss", "Dismiss")
+            .show()?;
+
+        // Held open until the notification is acted on or closed
+        std::thread::spawn(move || handle.wait_for_action(|action| if action == "snooze" {
+            let _ = requests.send(Request { command: Command::Snooze(SNOOZE_TIME), reply: None });
+        }));
+    }
+    #[cfg(target_os = "macos")]
+    {
+        let _ = requests;
+        notification.show()?;
+    }
+
+    Ok(())
+}
+
+
+/// Writes BEL to stderr `count` times in the background. Stderr is used so machine readable output
+/// on stdout stays clean, and it's usually the same terminal.
+fn ring_bell(count: u32, interval: std::time::Duration) -> std::thread::JoinHandle<()> {
+    std::thread::spawn(move || {
+        for i in 0..count {
+            if i > 0 {
+                std::thread::sleep(interval);
+            }
+
+            let mut stderr = std::io::stderr();
+            let _ = stderr.write_all(b"\x07").and_then(|_| stderr.flush());
+        }
+    })
+}
+
+/// Starts `command` with the system shell without waiting for it to finish. The label of the
+/// phase is passed in `$HOURGALS_LABEL`.
+fn spawn_shell(command: &str, label: Option<&str>) -> std::io::Result<()> {
+    let mut process = if cfg!(windows) {
+        let mut process = std::process::Command::new("cmd");
+        process.args(["/C", command]);
+        process
+    } else {
+        let mut process = std::process::Command::new("sh");
+        process.args(["-c", command]);
+        process
+    };
+
+    if let Some(label) = label {
+        process.env("HOURGALS_LABEL", label);
+    }
+
+    let mut child = process.spawn()?;
+
+    // Someone has to collect the exit status, or it lingers as a zombie until we exit
+    std::thread::spawn(move || child.wait());
+
+    Ok(())
+}
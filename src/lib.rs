@@ -0,0 +1,23 @@
+//! Falling sand simulation of an hourglass, rendered as text.
+//!
+//! ```
+//! use hourgals::Hourglass;
+//!
+//! let mut glass = Hourglass::new(7, 12);
+//! glass.fill_with_sand_from_top(0.5);
+//! glass.pinch();
+//! glass.settle_state(&mut rand::rng());
+//! println!("{}", glass);
+//! ```
+//!
+//! The sand is moved with any `rand::Rng`, which can also be a `&mut dyn rand::RngCore` picked at
+//! runtime. A seeded one, like `rand::rngs::SmallRng`, moves it the same way every time.
+//!
+//! Other ways to draw it than `Display`, like in color or as images, are in [`render`].
+
+pub mod hourglass;
+pub mod render;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+pub use hourglass::{CellView, GrainMove, Grid, Hourglass, HourglassBuilder, HourglassError, InvariantViolation, LayoutCell, SettleReport, Shape, WallStyle};
@@ -1,25 +1,115 @@
-pub mod hourglass;
+mod compositor;
+mod config;
+mod control;
+#[cfg(unix)]
+mod control_socket;
+#[cfg(feature = "dbus")]
+mod dbus;
+mod event_log;
+mod events;
+#[cfg(feature = "metrics")]
+mod metrics;
+mod mouse;
+mod output;
+mod recording;
+mod recurrence;
+mod session;
+#[cfg(unix)]
+mod signals;
+mod state_file;
+#[cfg(unix)]
+mod systemd;
+mod timer;
+mod watch;
+#[cfg(feature = "serve")]
+mod web;
+#[cfg(feature = "serve")]
+mod websocket;
 
-use clap::Parser;
-use chrono::{NaiveDateTime, NaiveTime, TimeDelta, ParseResult};
-use hourglass::Hourglass;
+use std::io::{IsTerminal, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use chrono::TimeDelta;
+use clap::{builder::TypedValueParser, CommandFactory, FromArgMatches, Parser, Subcommand};
+use hourgals::{Hourglass, SettleReport, Shape, WallStyle};
+use control::{Command, Request};
+use events::{BellPattern, EventActions};
+use output::{Announcer, Background, ClockOverlay, ColorChoice, DrawOptions, OutputMode, Progress, RenderBackend, TaskbarProgress, TerminalGuard, TitleSetter};
+use session::{Controller, Session, WakeAction};
+use timer::{format_time, parse_time, parse_timestamp, ClockWatch, Phase, ScaledClock, TimeRange, Timer, Zone};
 
 
 #[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
+#[command(version, about, long_about = None, args_conflicts_with_subcommands = true)]
 struct Args {
-    /// Start of time range. (today)
+    #[command(subcommand)]
+    subcommand: Option<CliCommand>,
+
+    // Without a subcommand, the options of `timer` are taken
+    #[command(flatten)]
+    timer: TimerArgs
+}
+
+#[derive(clap::Args, Debug)]
+struct TimerArgs {
+    // First, since the help headings of the flattened ones carry over to what comes after them
+    #[command(flatten)]
+    run: RunArgs,
+
+    #[command(flatten)]
+    time: TimeArgs
+}
+
+// When a timer runs. Only `timer` has these, the other ways of running work their times out themselves.
+// (doc comments here would become the description of the commands they're in)
+#[derive(clap::Args, Default, Debug)]
+#[command(next_help_heading = "Time", group(clap::ArgGroup::new("lengths").args(["length", "length_positional"])))]
+struct TimeArgs {
+    /// Same as `--length`, for the usual `hourgals 25m`.
+    #[arg(value_name = "LENGTH")]
+    length_positional: Option<String>,
+
+    /// Start of time range. (HH:MM[:SS], noon, or midnight for today, YYYY-MM-DD HH:MM[:SS] optionally followed by an offset like +02:00, now, a time from now like "in 2h" or +45m, or the next time it's a weekday and time like "fri 17:00")
     #[arg(long)]
     begin: Option<String>,
 
-    /// End of time range. (if this is less than begin, it's interpreted to be tomorrow)
-    #[arg(long)]
+    /// End of time range, in the same formats as begin. (a time without a date before begin or now is taken to be tomorrow)
+    #[arg(long, visible_alias = "until")]
     end: Option<String>,
 
-    /// Length of time range. (for example, 90s, 1m30s, 1y2d3h4m5s)
+    /// Length of time range. (for example, 90s, 1m30s, 1.5h, 2w, "1h 30m", "2 hours", or ISO 8601 like PT1H30M) Read from the first line of stdin if there's no range and it's piped.
     #[arg(long)]
     length: Option<String>,
 
+    /// Run several timers side by side, each with its own hourglass. (for example, tea:3m, or just 3m)
+    #[arg(long = "timer", conflicts_with_all = ["begin", "end", "lengths", "load_state", "save_state"])]
+    timers: Vec<String>,
+
+    /// Another timer to run after the first one, flipping the glass in between. Can be given more
+    /// than once. (for example, 2m, or break:2m)
+    #[arg(long, conflicts_with_all = ["timers", "load_state"])]
+    then: Vec<String>,
+
+    /// Run again on a schedule, waiting with the sand up until the next time. (an interval like 1h,
+    /// or a cron expression like "0 9 * * 1-5" or @hourly read in `--timezone`)
+    #[arg(long, requires = "lengths", conflicts_with_all = ["begin", "end", "repeat", "load_state"])]
+    every: Option<String>,
+
+    /// Resume a timer from a `--save-state` file. (the time and hourglass options are ignored)
+    #[arg(long)]
+    load_state: Option<PathBuf>,
+
+    /// Start the time over whenever FILE is modified, counting from when it was. (like the time since
+    /// the last save, or since a build came out)
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["begin", "end", "every", "load_state"])]
+    watch: Option<PathBuf>
+}
+
+// The shape of the glass and the sand in it
+#[derive(clap::Args, Debug)]
+#[command(next_help_heading = "Hourglass")]
+struct GlassArgs {
     /// Total width of the hourglass. (must be odd)
     #[arg(long, default_value_t = 7)]
     width: u32,
@@ -28,165 +118,1202 @@ struct Args {
     #[arg(long, default_value_t = 12)]
     height: u32,
 
+    /// Width of the opening between the two halves of the hourglass. (must be odd)
+    #[arg(long, default_value_t = 1)]
+    neck_width: u32,
+
+    /// Outline of the walls.
+    #[arg(long, default_value = "classic", value_parser = clap::builder::PossibleValuesParser::new(Shape::NAMES).map(|name| name.parse::<Shape>().unwrap()))]
+    shape: Shape,
+
+    /// Characters to draw the walls with. The unicode ones need a font with box drawing characters.
+    #[arg(long, default_value = "ascii", value_parser = clap::builder::PossibleValuesParser::new(WallStyle::NAMES).map(|name| name.parse::<WallStyle>().unwrap()))]
+    wall_style: WallStyle,
+
+    /// Columns the slanted walls go across per row. Terminal cells are about twice as tall as
+    /// they're wide, so `--aspect` on its own makes it 2, which looks like 45 degrees.
+    #[arg(long, value_name = "COLUMNS", num_args = 0..=1, default_value_t = 1, default_missing_value = "2", value_parser = clap::value_parser!(u8).range(1..=8))]
+    aspect: u8,
+
+    /// Grains of sand each cell can hold, up to 16. More make the sand level change more smoothly.
+    #[arg(long, default_value_t = Hourglass::DEFAULT_CELL_CAPACITY, value_parser = clap::value_parser!(u8).range(1..=Hourglass::MAX_CELL_CAPACITY as i64))]
+    cell_capacity: u8,
+
+    /// How much of the hourglass to fill with sand. 0 is no sand, 1 is completely fully.
+    #[arg(long, default_value_t = 0.75)]
+    fullness: f32,
+
+    /// Let the sand in the bottom pile up into a cone that now and then slides down. With
+    /// `--avalanche=false` it fills up in calmer, flatter layers.
+    #[arg(long, num_args = 0..=1, default_value_t = true, default_missing_value = "true", action = clap::ArgAction::Set)]
+    avalanche: bool,
+
+    /// How many cells a falling grain can drop in one step. More make the sand fall faster in tall
+    /// glasses, without raising `--steps-per-frame`.
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u8).range(1..))]
+    terminal_velocity: u8,
+
+    /// Put the glass in a wooden stand, with a cap, a base, and posts at the sides. It goes around
+    /// the glass, taking up 2 more columns and rows.
+    #[arg(long, default_value_t = false)]
+    decor: bool
+}
+
+// Everything about running that isn't when it happens
+#[derive(clap::Args, Debug)]
+struct RunArgs {
+    /// Time zone of begin and end, unless they have an offset. (an IANA name like America/New_York, or local)
+    #[arg(long, default_value = "local")]
+    timezone: Zone,
+
     /// Visual updates per second.
     #[arg(long, default_value_t = 20.0)]
     frames_per_sec: f64,
 
+    /// Make time pass this many times as fast, like 120x to see an hour go by in 30 seconds.
+    #[arg(long, default_value = "1x", value_parser = timer::parse_speed)]
+    time_scale: f64,
+
     /// Simulation updates per visual update. (10 frames per sec * 5 steps per frame = 50 steps per sec)
     #[arg(long, default_value_t = 2)]
     steps_per_frame: u32,
 
-    /* TODO
-    /// Whether to flip the hourglass over once the time is elapsed.
+    /// Whether to flip the hourglass over once the time is elapsed. (or start over after the last phase)
     #[arg(long, default_value_t = false)]
     repeat: bool,
-    */
 
-    /// How much of the hourglass to fill with sand. 0 is no sand, 1 is completely fully.
-    #[arg(long, default_value_t = 0.75)]
-    fullness: f32
+    /// Start paused, until Enter is pressed, SIGUSR1 is received, or a resume command is sent.
+    #[arg(long, default_value_t = false)]
+    paused: bool,
+
+    /// What to do when the clock jumps, like when the system wakes up from suspend.
+    #[arg(long, value_enum, default_value_t = WakeAction::CatchUp)]
+    on_wake: WakeAction,
+
+    /// How far to open the neck to keep the sand up with the time.
+    #[arg(long, value_enum, default_value_t = Controller::Proportional)]
+    controller: Controller,
+
+    /// Let the neck clog for a moment now and then, and the sand come down in a burst once it
+    /// breaks free. It still ends on time.
+    #[arg(long, default_value_t = false)]
+    jams: bool,
+
+    /// Check after every step that no sand got lost, made up, or put where it can't be, and stop
+    /// with a dump of the glass if it did.
+    #[arg(long, default_value_t = false)]
+    debug_sim: bool,
+
+    /// Exit once the time is up and all the sand has settled in the bottom. (never happens with `--repeat` or `--every`)
+    #[arg(long, default_value_t = false)]
+    exit_when_done: bool,
+
+    /// Keep going once the time is up, showing how far over it is. (like "+3:12 over")
+    #[arg(long, default_value_t = false, conflicts_with_all = ["repeat", "exit_when_done"])]
+    overtime: bool,
+
+    /// Use the settings of a preset as defaults. (see the `presets` subcommand)
+    #[arg(long)]
+    preset: Option<String>,
+
+    /// Name of the timer, shown above the hourglass and in notifications.
+    #[arg(long)]
+    label: Option<String>,
+
+    /// Show a desktop notification when the time is up.
+    #[cfg(feature = "notify")]
+    #[arg(long, default_value_t = false)]
+    notify: bool,
+
+    /// Ring the terminal bell when the time is up.
+    #[arg(long, default_value_t = false)]
+    bell: bool,
+
+    /// How many times `--bell` rings.
+    #[arg(long, default_value_t = 3)]
+    bell_count: u32,
+
+    /// Seconds between rings of `--bell`.
+    #[arg(long, default_value_t = 0.5)]
+    bell_interval: f64,
+
+    /// Remaining times to ring the bell once at as a warning. (for example, 1m,10s)
+    #[arg(long, value_delimiter = ',')]
+    bell_at: Vec<String>,
+
+    /// Shell command to run when the time is up. (the label is in $HOURGALS_LABEL)
+    #[arg(long)]
+    exec: Option<String>,
+
+    /// Shell command to run when the hourglass is flipped over, by `--repeat` or SIGUSR2.
+    #[arg(long)]
+    exec_on_flip: Option<String>,
+
+    /// Shell command to run when the time gets to a point, given as a percentage of it that's passed
+    /// or a time that's left. Can be given more than once. (for example, 50%='notify-send halfway', or 5m='echo 5 minutes left')
+    #[arg(long, value_name = "POINT=COMMAND", value_parser = events::parse_hook)]
+    at: Vec<(events::HookPoint, String)>,
+
+    /// Append a line of JSON to FILE for everything that happens to the timers, like pausing or the
+    /// time running out.
+    #[arg(long, value_name = "FILE")]
+    log: Option<PathBuf>,
+
+    /// Print how the run went on exit, like how long it took and how far the sand was off from the time.
+    #[arg(long, default_value_t = false)]
+    stats: bool,
+
+    /// What to write to stdout every frame.
+    #[arg(long, value_enum, default_value_t = OutputMode::Terminal)]
+    output: OutputMode,
+
+    /// How often to print the glass when stdout isn't a terminal, each time followed by a separator line.
+    #[arg(long, default_value = "10s")]
+    snapshot_interval: String,
+
+    /// When to use colors. (NO_COLOR, CLICOLOR and CLICOLOR_FORCE are respected with auto)
+    #[arg(long, value_enum, default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+
+    /// Draw sand that just moved in brighter colors, fading over `--fade-time`.
+    #[arg(long, default_value_t = false)]
+    color_by_age: bool,
+
+    /// How long moving sand stays highlighted with `--color-by-age`.
+    #[arg(long, default_value = "3s")]
+    fade_time: String,
+
+    /// Draw grains falling straight down with this character instead, like | or •.
+    #[arg(long)]
+    falling_glyph: Option<char>,
+
+    /// Kick up a speck of dust (˙) next to grains as they land.
+    #[arg(long, default_value_t = false)]
+    dust: bool,
+
+    /// Draw sand with this instead of dots, like # or an emoji. Wide ones make the whole glass wider.
+    #[arg(long, value_parser = output::parse_glyph)]
+    sand_glyph: Option<String>,
+
+    /// Draw every cell two columns wide, so the glass isn't stretched out tall. (like `::` for a
+    /// full cell and `. ` for a half full one)
+    #[arg(long, default_value_t = false)]
+    wide: bool,
+
+    /// What to draw behind the glasses: default, transparent (writes nothing there), a color like magenta, or #rrggbb.
+    #[arg(long, default_value = "default")]
+    background: Background,
+
+    /// Draw the time left in big digits, over the bottom of the glass or below it.
+    #[arg(long, value_enum, num_args = 0..=1, default_missing_value = "over")]
+    clock_overlay: Option<ClockOverlay>,
+
+    /// Show how far along the time and the sand are below the glass, in percent and as a bar.
+    #[arg(long, default_value_t = false)]
+    show_percent: bool,
+
+    /// Show when the time will be up below the glass, in `--timezone`.
+    #[arg(long, default_value_t = false)]
+    show_eta: bool,
+
+    /// Turn the glass yellow when this much time is left, and red for the last half of it. (a time like 2m, or a percentage of the time like 10%)
+    #[arg(long, value_parser = events::parse_hook_point)]
+    warn: Option<events::HookPoint>,
+
+    /// Don't flash the glass when the time runs out.
+    #[arg(long, default_value_t = false)]
+    no_flash: bool,
+
+    /// Draw each timer on one line, as a bar of sand with the time left. This is also done when the
+    /// glasses don't fit in the terminal.
+    #[arg(long, default_value_t = false)]
+    compact: bool,
+
+    /// Click the glass to pause, and drag down or double click to flip it. (space, f, Enter, and q work too)
+    #[arg(long, default_value_t = false)]
+    mouse: bool,
+
+    /// Show the label and time left in the terminal title.
+    #[arg(long, default_value_t = false)]
+    title: bool,
+
+    /// Show the progress on the taskbar, in terminals that support it like Windows Terminal.
+    #[arg(long, default_value_t = false)]
+    taskbar_progress: bool,
+
+    /// Write plain lines like "15 minutes remaining" instead of drawing, for screen readers.
+    #[arg(long, default_value_t = false, conflicts_with = "headless")]
+    accessible: bool,
+
+    /// How often `--accessible` tells the time left.
+    #[arg(long, default_value = "1m")]
+    announce_interval: String,
+
+    /// Don't write frames at all, just keep time and react to events. (for cron jobs and the like)
+    #[arg(long, default_value_t = false)]
+    headless: bool,
+
+    /// Don't simulate the sand either, so phases end as soon as their time is up.
+    #[arg(long, default_value_t = false, requires = "headless")]
+    no_simulation: bool,
+
+    /// File to periodically write the timer and hourglass to, so it can be resumed with `--load-state`.
+    #[arg(long)]
+    save_state: Option<PathBuf>,
+
+    /// Seconds between writes to the `--save-state` file.
+    #[arg(long, default_value_t = 5.0)]
+    save_interval: f64,
+
+    /// Record the run into this file, so it can be played back with the `replay` subcommand. A file
+    /// ending in .hgr only gets what the glasses were built from and what was done to them, which
+    /// is much smaller and good for bug reports, but doesn't have the timers.
+    #[arg(long, conflicts_with = "no_simulation")]
+    record: Option<PathBuf>,
+
+    /// Serve Prometheus metrics over HTTP at this address, under /metrics. (for example, 127.0.0.1:9400)
+    #[cfg(feature = "metrics")]
+    #[arg(long)]
+    metrics_listen: Option<std::net::SocketAddr>,
+
+    /// Serve a page showing the glasses at this address, for a browser to follow along. (for example, 127.0.0.1:8080)
+    #[cfg(feature = "serve")]
+    #[arg(long)]
+    serve: Option<std::net::SocketAddr>,
+
+    /// Stream the progress over WebSocket at this address, as a JSON message per frame. (for example, 127.0.0.1:8081)
+    #[cfg(feature = "serve")]
+    #[arg(long)]
+    ws_listen: Option<std::net::SocketAddr>,
+
+    /// Have the glasses drawn as text in the `--ws-listen` messages too.
+    #[cfg(feature = "serve")]
+    #[arg(long, requires = "ws_listen", default_value_t = false)]
+    ws_glasses: bool,
+
+    /// Serve the timer on the session bus, as org.hourgals.Timer with Pause, Resume, Flip, and AddTime.
+    #[cfg(feature = "dbus")]
+    #[arg(long, default_value_t = false)]
+    dbus: bool,
+
+    /// Listen for commands on this Unix domain socket. (see the `ctl` subcommand)
+    #[cfg(unix)]
+    #[arg(long)]
+    control_socket: Option<PathBuf>,
+
+    #[command(flatten)]
+    glass: GlassArgs
 }
 
+#[derive(Subcommand, Debug)]
+enum CliCommand {
+    /// Run a timer, which is also what happens without a subcommand.
+    Timer(TimerArgs),
+
+    /// Alternate between work and breaks, with a long break at the end.
+    Pomodoro {
+        /// Length of work phases.
+        #[arg(long, default_value = "25m")]
+        work: String,
+
+        /// Length of the breaks between work phases.
+        #[arg(long = "break", default_value = "5m")]
+        short_break: String,
+
+        /// Length of the break after the last work phase.
+        #[arg(long, default_value = "15m")]
+        long_break: String,
+
+        /// Number of work phases.
+        #[arg(long, default_value_t = 4, value_parser = clap::value_parser!(u32).range(1..))]
+        cycles: u32,
+
+        #[command(flatten)]
+        run: RunArgs
+    },
+
+    /// Run the phases in a file back to back, one per line like `warmup 5m` or `interval 1m x8`,
+    /// where `xN` repeats it N times.
+    Schedule {
+        /// File with the phases. Empty lines and ones starting with # are skipped.
+        file: PathBuf,
+
+        #[command(flatten)]
+        run: RunArgs
+    },
+
+    /// Count up from when it's started, with no end. The sand flows slower and slower, so there's
+    /// always some left.
+    Stopwatch {
+        /// Time after which half of the sand is in the bottom.
+        #[arg(long, default_value = "15m")]
+        half_at: String,
+
+        #[command(flatten)]
+        run: RunArgs
+    },
+
+    /// Print the hourglass without running a timer, for pictures and the like.
+    Render {
+        /// How much of the sand has run down, from 0 to 1.
+        #[arg(long, default_value_t = 0.0)]
+        progress: f64,
+
+        /// Print this many frames from `--progress` to all of the sand being down instead, each
+        /// followed by a separator line.
+        #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+        frames: u32,
+
+        /// Seed of the sand, so the same picture can be made again.
+        #[arg(long)]
+        seed: Option<u64>,
+
+        /// How to draw the glass.
+        #[arg(long, value_enum, default_value_t = RenderBackend::Plain)]
+        renderer: RenderBackend,
+
+        #[command(flatten)]
+        glass: GlassArgs
+    },
+
+    /// Play back a run recorded with `--record`, with the sand moving the same way it did.
+    Replay {
+        file: PathBuf,
+
+        /// How much faster than it was recorded to play it, like 4x.
+        #[arg(long, default_value = "1x", value_parser = timer::parse_speed)]
+        speed: f64
+    },
+
+    /// Run the simulation without drawing it, and print how fast it went.
+    Bench {
+        /// Steps to run for. The glass is flipped whenever the sand is done.
+        #[arg(long, default_value_t = 100_000)]
+        steps: u32,
+
+        /// Seed of the sand, so runs can be compared.
+        #[arg(long)]
+        seed: Option<u64>,
+
+        #[command(flatten)]
+        glass: GlassArgs
+    },
+
+    /// List the presets usable with `--preset`.
+    Presets,
+
+    /// Print a script that makes the shell complete the subcommands and options, and the presets
+    /// there are when it's made. (for example, `hourgals completions fish > ~/.config/fish/completions/hourgals.fish`)
+    Completions {
+        shell: clap_complete::Shell
+    },
 
-fn parse_timestamp(timestamp: &str) -> ParseResult<NaiveDateTime> {
-    Ok(NaiveDateTime::new(
-        chrono::Local::now().naive_local().date(),
-        NaiveTime::parse_from_str(timestamp, "%H:%M:%S").or_else(|_| {
-            NaiveTime::parse_from_str(timestamp, "%H:%M")
-        })?
-    ))
+    /// Print one line about a running instance in the `--output` format, for status bars like
+    /// tmux's `status-right`. (the terminal format prints the tmux one)
+    Status {
+        /// Ask the instance listening on this `--control-socket`.
+        #[cfg(unix)]
+        #[arg(long, required_unless_present = "state", conflicts_with = "state")]
+        socket: Option<PathBuf>,
+
+        /// Read the file an instance writes with `--save-state`.
+        #[arg(long)]
+        state: Option<PathBuf>,
+
+        /// What kind of line to print.
+        #[arg(long, value_enum, default_value_t = OutputMode::Terminal)]
+        output: OutputMode,
+
+        /// Name of the timer, since the state file doesn't have it.
+        #[arg(long)]
+        label: Option<String>
+    },
+
+    /// Send a command to an instance started with `--control-socket`, and print its answer.
+    #[cfg(unix)]
+    Ctl {
+        /// Socket the instance is listening on.
+        #[arg(long)]
+        socket: PathBuf,
+
+        /// One of pause, resume, toggle, flip, add TIME, snooze TIME, status, or quit.
+        #[arg(required = true, num_args = 1..)]
+        command: Vec<String>
+    }
 }
 
-fn parse_time(time: &str) -> Result<TimeDelta, &'static str> {
-    fn try_parse_to_seconds(field: &str) -> Result<u64, &'static str> {
-        let chars: Vec<char> = field.chars().collect();
-        if chars.len() < 2 {
-            return Err("time part must be at least 2 chars long");
+
+fn main() -> Result<ExitCode, Box<dyn std::error::Error>> {
+    match run_main() {
+        // Whatever was reading the output stopped, like `head` does once it has enough
+        Err(err) if is_broken_pipe(err.as_ref()) => Ok(ExitCode::SUCCESS),
+        result => result
+    }
+}
+
+/// Whether `err`, or what caused it, is writing to a pipe with nothing reading from it anymore.
+fn is_broken_pipe(err: &(dyn std::error::Error + 'static)) -> bool {
+    std::iter::successors(Some(err), |err| err.source())
+        .any(|err| err.downcast_ref::<std::io::Error>().is_some_and(|err| err.kind() == std::io::ErrorKind::BrokenPipe))
+}
+
+fn run_main() -> Result<ExitCode, Box<dyn std::error::Error>> {
+    let config = config::load()?;
+
+    // The preset can only be picked out once the command line is parsed, after which it changes the defaults
+    let mut matches = config::apply_defaults(Args::command(), &config::settings(&config, None)?)?.get_matches();
+    if let Some(preset) = preset_name(&matches) {
+        matches = config::apply_defaults(Args::command(), &config::settings(&config, Some(&preset))?)?.get_matches();
+    }
+
+    let args = Args::from_arg_matches(&matches)?;
+
+    let (run, time) = match &args.subcommand {
+        None => (&args.timer.run, Some(&args.timer.time)),
+        Some(CliCommand::Timer(timer)) => (&timer.run, Some(&timer.time)),
+        Some(CliCommand::Pomodoro { run, .. } | CliCommand::Schedule { run, .. } | CliCommand::Stopwatch { run, .. }) => (run, None),
+        Some(CliCommand::Render { progress, frames, seed, renderer, glass }) => {
+            render(glass, *progress, *frames, *seed, *renderer)?;
+            return Ok(ExitCode::SUCCESS);
+        },
+        Some(CliCommand::Replay { file, speed }) => {
+            let options = DrawOptions {
+                fresh_sand_steps: None,
+                falling_glyph: None,
+                dust: false,
+                sand_glyph: None,
+                wide: false,
+                background: Background::Default,
+                clock_overlay: None,
+                show_percent: false,
+                eta_zone: None,
+                compact: false,
+                warn_at: None,
+                flash: true,
+                overtime_color: true
+            };
+            recording::replay(file, *speed, &options)?;
+            return Ok(ExitCode::SUCCESS);
+        },
+        Some(CliCommand::Bench { steps, seed, glass }) => {
+            bench(glass, *steps, *seed)?;
+            return Ok(ExitCode::SUCCESS);
+        },
+        Some(CliCommand::Completions { shell }) => {
+            let presets: Vec<String> = config::presets(&config)?.keys().cloned().collect();
+            let (mut command, _) = config::mut_arg_everywhere(Args::command(), "preset", &|arg| arg.value_parser(clap::builder::PossibleValuesParser::new(&presets)));
+            // Written out afterwards, as `generate` panics when it can't write
+            let mut completions = Vec::<u8>::new();
+            clap_complete::generate(*shell, &mut command, "hourgals", &mut completions);
+            std::io::stdout().write_all(&completions)?;
+            return Ok(ExitCode::SUCCESS);
+        },
+        Some(CliCommand::Presets) => {
+            for (name, settings) in config::presets(&config)? {
+                writeln!(std::io::stdout(), "{}: {}", name, settings)?;
+            }
+            return Ok(ExitCode::SUCCESS);
+        },
+        #[cfg(unix)]
+        Some(CliCommand::Ctl { socket, command }) => {
+            let answer = control_socket::send(socket, &command.join(" "))?;
+            writeln!(std::io::stdout(), "{}", answer)?;
+            return Ok(if answer.starts_with("error:") { ExitCode::FAILURE } else { ExitCode::SUCCESS });
+        },
+        Some(CliCommand::Status { #[cfg(unix)] socket, state, output, label }) => {
+            #[cfg(unix)]
+            if let Some(socket) = socket {
+                let answer = control_socket::send(socket, "status")?;
+                let progresses: Vec<Progress> = if answer.starts_with('[') {
+                    serde_json::from_str(&answer)?
+                } else {
+                    vec![serde_json::from_str(&answer)?]
+                };
+
+                writeln!(std::io::stdout(), "{}", output::status_line(*output, &progresses.iter().collect::<Vec<_>>())?)?;
+                return Ok(ExitCode::SUCCESS);
+            }
+
+            let Some(state) = state else {
+                return Err("`status` needs `--state`".into());
+            };
+            let (timer, glass) = state_file::load(state)?;
+            let phase = Phase { label: label.clone(), duration: timer.range.duration };
+            let progress = Session::new(timer, glass, vec![phase], Vec::new(), Vec::new()).measure(chrono::Utc::now());
+
+            writeln!(std::io::stdout(), "{}", output::status_line(*output, &[&progress])?)?;
+            return Ok(ExitCode::SUCCESS);
         }
+    };
+    // Other ways of running than `timer` have none of the time options set
+    let no_time = TimeArgs::default();
+    let time = time.unwrap_or(&no_time);
 
-        let (unit, number_chars): (&char, &[char]) = chars.split_last().expect("expected vector with length of at least 2 to have a last element");
-        let number: u64 = match number_chars.iter().collect::<String>().parse::<u64>() {
-            Ok(x) => x,
-            Err(_) => return Err("cannot parse time part number")
-        };
+    // The sand is moved with this one generator. It isn't used until everything's set up, so a
+    // recording can make the same one from the seed.
+    let seed: u64 = rand::random();
+    let mut rng: rand::rngs::SmallRng = rand::SeedableRng::seed_from_u64(seed);
+
+    let warnings = run.bell_at.iter().map(|time| parse_time(time)).collect::<Result<Vec<_>, _>>()?;
+    let hooks: Vec<events::HookPoint> = run.at.iter().map(|(point, _)| *point).collect();
 
-        let multiplier = match *unit {
-            's' => 1,
-            'm' => 60,
-            'h' => 60 * 60,
-            'd' => 60 * 60 * 24,
-            'y' => 60 * 60 * 24 * 365,
-            _ => return Err("invalid time unit (valid units are s, d, h, d, and y)")
+    // Glasses too big for the terminal are made smaller instead of wrapping around, also when it's
+    // resized. The ones that can't be get drawn on one line.
+    let fit_to_terminal = run.output == OutputMode::Terminal && !run.compact && !run.headless && !run.accessible && std::io::stdout().is_terminal();
+    let mut terminal_size = crossterm::terminal::size().ok();
+
+    let mut sessions = Vec::<Session>::new();
+    // What each glass was built from, for compact recordings. Loaded ones weren't built.
+    let mut glass_specs = Vec::<Option<recording::GlassSpec>>::new();
+    if let Some(path) = &time.load_state {
+        let (timer, mut glass) = state_file::load(path)?;
+        // Goes by the flags, as state saved before there were avalanches or faster falls doesn't say
+        glass.set_avalanches(run.glass.avalanche);
+        glass.set_terminal_velocity(run.glass.terminal_velocity);
+        let phase = Phase { label: run.label.clone(), duration: timer.range.duration };
+        let mut session = Session::new(timer, glass, vec![phase], warnings, hooks);
+        session.simulate = !run.no_simulation;
+        session.controller = run.controller;
+        session.jams = run.jams;
+        session.check_invariants = run.debug_sim;
+        session.overtime = run.overtime;
+        sessions.push(session);
+        glass_specs.push(None);
+    } else {
+        let mut timers = Vec::<(TimeRange, Vec<Phase>)>::new();
+        let mut count_up: Option<TimeDelta> = None;
+
+        if let Some(CliCommand::Pomodoro { work, short_break, long_break, cycles, .. }) = &args.subcommand {
+            let phases = timer::pomodoro_phases(parse_time(work)?, parse_time(short_break)?, parse_time(long_break)?, *cycles);
+            timers.push((TimeRange::try_from_args(None, None, Some(phases[0].duration))?, phases));
+        } else if let Some(CliCommand::Schedule { file, .. }) = &args.subcommand {
+            let text = std::fs::read_to_string(file).map_err(|err| format!("can't read {}: {}", file.display(), err))?;
+            let phases = timer::parse_schedule(&text).map_err(|err| format!("{}: {}", file.display(), err))?;
+            timers.push((TimeRange::try_from_args(None, None, Some(phases[0].duration))?, phases));
+        } else if let Some(CliCommand::Stopwatch { half_at, .. }) = &args.subcommand {
+            if !run.bell_at.is_empty() || run.save_state.is_some() {
+                return Err("`--bell-at` and `--save-state` can't be used with `stopwatch`".into());
+            }
+
+            count_up = Some(parse_time(half_at)?);
+            timers.push((
+                TimeRange { start: chrono::Utc::now(), duration: TimeDelta::zero() },
+                vec![Phase { label: run.label.clone(), duration: TimeDelta::zero() }]
+            ));
+        } else if !time.timers.is_empty() {
+            for timer_arg in &time.timers {
+                let phase = timer::parse_labeled_time(timer_arg)?;
+                timers.push((TimeRange::try_from_args(None, None, Some(phase.duration))?, vec![phase]));
+            }
+        } else {
+            // A length on its own can only come from the command line, so it goes over a `--length` that
+            // a preset or the config file set
+            let mut length = match (&time.length_positional, &time.length) {
+                (Some(length_arg), _) => Some(parse_time(length_arg).map_err(|err| match Args::command().find_subcommand(length_arg) {
+                    Some(_) => format!("invalid length `{}`: {} (subcommands go before any options)", length_arg, err),
+                    None => format!("invalid length `{}`: {}", length_arg, err)
+                })?),
+                (None, Some(length_arg)) => Some(parse_time(length_arg)?),
+                (None, None) => None
+            };
+            // Like `echo 25m | hourgals`, for pickers that print what was picked. The lines after it
+            // are still read as commands.
+            if time.begin.is_none() && time.end.is_none() && length.is_none() && !std::io::stdin().is_terminal() {
+                let mut line = String::new();
+                std::io::stdin().read_line(&mut line)?;
+                if line.trim().is_empty() {
+                    return Err("no time range given, and no length came in on stdin".into());
+                }
+                length = Some(parse_time(line.trim()).map_err(|err| format!("invalid length `{}` from stdin: {}", line.trim(), err))?);
+            }
+
+            let time_range = TimeRange::try_from_args(
+                if let Some(begin_arg) = &time.begin { Some(parse_timestamp(begin_arg, run.timezone)?) } else { None },
+                if let Some(end_arg) = &time.end { Some(parse_timestamp(end_arg, run.timezone)?) } else { None },
+                length
+            )?;
+            let mut phases = vec![Phase { label: run.label.clone(), duration: time_range.duration }];
+            for then_arg in &time.then {
+                phases.push(timer::parse_labeled_time(then_arg)?);
+            }
+            // Which one it's at still shows when they aren't named
+            let count = phases.len();
+            if count > 1 {
+                for (i, phase) in phases.iter_mut().enumerate() {
+                    phase.label.get_or_insert_with(|| format!("{}/{}", i + 1, count));
+                }
+            }
+            timers.push((time_range, phases));
+        }
+
+        let (width, height) = match crossterm::terminal::size() {
+            Ok(size) if fit_to_terminal => {
+                let labeled = timers.iter().any(|(_, phases)| phases.iter().any(|phase| phase.label.is_some()));
+                fitted_size(run, timers.len(), labeled, count_up.is_some(), size)
+            },
+            _ => (run.glass.width as usize, run.glass.height as usize)
         };
 
-        Ok(number * multiplier)
+        for (time_range, phases) in timers {
+            let spec = recording::GlassSpec {
+                width,
+                height,
+                neck_width: run.glass.neck_width as usize,
+                shape: run.glass.shape,
+                cell_capacity: run.glass.cell_capacity,
+                fullness: run.glass.fullness / 2.0,
+                pinched: true,
+                avalanches: run.glass.avalanche,
+                terminal_velocity: run.glass.terminal_velocity,
+                decor: run.glass.decor,
+                wall_style: run.glass.wall_style,
+                aspect: run.glass.aspect,
+                seed: rand::random()
+            };
+            let (glass, settled) = spec.build().map_err(|err| format!("can't make the hourglass: {}", err))?;
+            if !settled.converged {
+                eprintln!("the sand didn't come to rest in {} steps, starting with it still moving", settled.steps);
+            }
+
+            let mut session = Session::new(Timer::new(time_range), glass, phases, warnings.clone(), hooks.clone());
+            if let Some(every) = &time.every {
+                let recurrence = recurrence::Recurrence::parse(every, run.timezone)?;
+                // An interval starts right away, a cron expression waits for the first time it matches
+                if let recurrence::Recurrence::Cron(..) = recurrence {
+                    let now = chrono::Utc::now();
+                    let Some(start) = recurrence.next(now, now) else {
+                        return Err(format!("`{}` doesn't happen in the next few years", every).into());
+                    };
+                    session.timer.range.start = start;
+                }
+                session.recurrence = Some(recurrence);
+            }
+            session.count_up = count_up;
+            session.simulate = !run.no_simulation;
+            session.controller = run.controller;
+            session.jams = run.jams;
+            session.check_invariants = run.debug_sim;
+            session.overtime = run.overtime;
+            sessions.push(session);
+            glass_specs.push(Some(spec));
+        }
     }
 
-    let mut total_seconds: u64 = 0;
-    for field in time.split_inclusive(|ch: char| !ch.is_digit(10)) {
-        total_seconds += try_parse_to_seconds(field)?;
+    // Redrawing in place doesn't work in a pipe or a log, so the glass gets printed every now and then instead
+    let snapshots = run.output == OutputMode::Terminal && !std::io::stdout().is_terminal();
+    let snapshot_interval = parse_time(&run.snapshot_interval)?.to_std()?;
+    let mut last_snapshot: Option<std::time::Instant> = None;
+
+    let color = run.color.enabled();
+    let draw_options = DrawOptions {
+        fresh_sand_steps: match run.color_by_age && color {
+            true => Some((parse_time(&run.fade_time)?.num_milliseconds() as f64 / 1000.0 * run.frames_per_sec * run.steps_per_frame as f64).max(1.0) as u64),
+            false => None
+        },
+        falling_glyph: run.falling_glyph,
+        dust: run.dust,
+        sand_glyph: run.sand_glyph.clone(),
+        wide: run.wide,
+        background: match &run.background {
+            Background::Color(_) if !color => Background::Default,
+            // Nothing's drawn over the last snapshot, so the glass has to have its spaces
+            Background::Transparent if snapshots => Background::Default,
+            background => background.clone()
+        },
+        clock_overlay: run.clock_overlay,
+        show_percent: run.show_percent,
+        eta_zone: run.show_eta.then_some(run.timezone),
+        compact: run.compact,
+        warn_at: run.warn.filter(|_| color),
+        // Snapshots are too far apart to catch it
+        flash: !run.no_flash && !snapshots,
+        overtime_color: run.overtime && color
+    };
+
+    let save_interval = std::time::Duration::from_secs_f64(run.save_interval);
+    let mut last_save: Option<std::time::Instant> = None;
+
+    #[cfg(feature = "notify")]
+    let (notification_requests, picked_notification_actions) = std::sync::mpsc::channel();
+    let mut actions = EventActions {
+        #[cfg(feature = "notify")]
+        notify: run.notify,
+        #[cfg(feature = "notify")]
+        notification_requests,
+        bell: run.bell.then(|| BellPattern {
+            count: run.bell_count,
+            interval: std::time::Duration::from_secs_f64(run.bell_interval)
+        }),
+        bell_on_warning: !run.bell_at.is_empty(),
+        exec: run.exec.clone(),
+        exec_on_flip: run.exec_on_flip.clone(),
+        hooks: run.at.iter().map(|(_, command)| command.clone()).collect(),
+        log: run.log.as_deref().map(event_log::EventLog::open).transpose()?,
+        ringing: Vec::new()
+    };
+
+    #[cfg(unix)]
+    let mut signals = signals::Signals::new()?;
+
+    #[cfg(unix)]
+    let control_socket = match &run.control_socket {
+        Some(path) => Some(control_socket::ControlSocket::bind(path)?),
+        None => None
+    };
+
+    #[cfg(feature = "dbus")]
+    let dbus = match run.dbus {
+        true => Some(dbus::DbusService::connect().map_err(|err| format!("can't connect to the session bus: {}", err))?),
+        false => None
+    };
+
+    #[cfg(feature = "metrics")]
+    let mut metrics = match run.metrics_listen {
+        Some(address) => Some(metrics::MetricsServer::bind(address)?),
+        None => None
+    };
+
+    #[cfg(feature = "serve")]
+    let web = match run.serve {
+        Some(address) => Some(web::WebServer::bind(address)?),
+        None => None
+    };
+    #[cfg(feature = "serve")]
+    let websocket = match run.ws_listen {
+        Some(address) => Some(websocket::WebSocketServer::bind(address, run.ws_glasses)?),
+        None => None
+    };
+
+    // Other programs can drive the timer by writing commands into a pipe
+    let interactive = run.output == OutputMode::Terminal && !run.headless && !run.accessible && std::io::stdin().is_terminal();
+    let mouse_enabled = run.mouse && interactive;
+    // Windows has no signals, so keys are read in raw mode for Ctrl+C to quit cleanly
+    let keys_enabled = mouse_enabled || (cfg!(windows) && interactive);
+    let stdin_requests = if std::io::stdin().is_terminal() {
+        // With the keys read in raw mode, Enter comes in as a key instead
+        (run.paused && !keys_enabled).then(control::resume_on_enter)
+    } else {
+        Some(control::read_stdin_commands())
+    };
+
+    let terminal = if run.headless || run.accessible || snapshots { None } else { Some(TerminalGuard::new(run.output)?) };
+    // Dropped before the terminal, so raw mode is off when the cursor comes back
+    let mouse = if keys_enabled { Some(mouse::MouseInput::new(mouse_enabled)?) } else { None };
+    let mut announcer = match run.accessible {
+        true => Some(Announcer::new(parse_time(&run.announce_interval)?.num_milliseconds() as f64 / 1000.0)),
+        false => None
+    };
+    let mut title = run.title.then(TitleSetter::new);
+    let mut taskbar_progress = run.taskbar_progress.then(TaskbarProgress::new);
+
+    let mut recorder = match &run.record {
+        Some(path) => {
+            for session in &mut sessions {
+                session.history = Some(Vec::new());
+            }
+            let glasses: Vec<&Hourglass> = sessions.iter().map(|session| &session.glass).collect();
+            Some(recording::Recorder::create(path, run.frames_per_sec, seed, &glasses, &glass_specs)?)
+        },
+        None => None
+    };
+
+    // Like when `--begin` is in the past. Loaded glasses are already as far as they should be.
+    let now = chrono::Utc::now();
+    for (session, spec) in sessions.iter_mut().zip(&glass_specs) {
+        if spec.is_some() {
+            session.skip_to_time(now, &mut rng);
+        }
     }
 
-    Ok(TimeDelta::seconds(total_seconds.try_into().unwrap()))
-}
+    let mut watch = time.watch.as_deref().map(watch::FileWatch::new);
+    // A file that's already there was modified before the timer started. Moving the sand for it
+    // gets recorded, so it's done with the generator of the sand.
+    if let Some(modified) = watch.as_ref().and_then(watch::FileWatch::modified) {
+        let now = chrono::Utc::now();
+        for session in &mut sessions {
+            session.restart(modified.min(now), now, &mut rng);
+        }
+    }
 
+    if run.paused {
+        let now = chrono::Utc::now();
+        for session in &mut sessions {
+            session.timer.pause(now);
+        }
+    }
 
-struct TimeRange {
-    start: NaiveDateTime,
-    duration: TimeDelta
-}
+    // Done to the glasses since the last frame was recorded, in the order the generator was used
+    let mut history = Vec::<(usize, recording::GlassOp)>::new();
+    let take_history = |sessions: &mut [Session], history: &mut Vec<(usize, recording::GlassOp)>| {
+        for (index, session) in sessions.iter_mut().enumerate() {
+            history.extend(session.take_history().into_iter().map(|op| (index, op)));
+        }
+    };
 
-impl TimeRange {
+    // Everything's set up that could go wrong at the start
+    #[cfg(unix)]
+    let mut systemd = systemd::SystemdNotifier::from_env()?;
+    #[cfg(unix)]
+    if let Some(systemd) = &systemd {
+        systemd.ready()?;
+    }
+
+    for session in &sessions {
+        actions.log(event_log::LogEvent::started(session.timer.range.duration), session.phase())?;
+    }
 
-    pub fn try_from_args(begin: Option<NaiveDateTime>, end: Option<NaiveDateTime>, length: Option<TimeDelta>) -> Result<TimeRange, &'static str> {
-        let now = chrono::Local::now().naive_local();
+    let run_start = std::time::Instant::now();
+    let mut frame_count: u64 = 0;
 
-        match (&begin, &end, &length) {
-            (None, None, None) => Err("must define time range with some combination of `begin`, `end`, and `length`"),
-            (None, None, Some(length)) => Ok(TimeRange {
-                start: now,
-                duration: *length
-            }),
-            (None, Some(end), None) => Ok(TimeRange {
-                start: now,
-                duration: *end - now
-            }),
-            (None, Some(end), Some(length)) => Ok(TimeRange {
-                start: *end - *length,
-                duration: *length
-            }),
-            (Some(_), None, None) => Err("must provide duration with `end` or `length`"),
-            (Some(begin), None, Some(length)) => Ok(TimeRange {
-                start: *begin,
-                duration: *length
-            }),
-            (Some(begin), Some(end), None) => Ok(TimeRange {
-                start: *begin,
-                duration: if end > begin {
-                    *end - *begin
-                } else {
-                    (*end + TimeDelta::days(1)) - *begin
+    let mut clock = ClockWatch::new(chrono::Utc::now());
+    let scaled_clock = ScaledClock::new(chrono::Utc::now(), run.time_scale);
+
+    loop {
+        let wall_now = chrono::Utc::now();
+        let now = scaled_clock.at(wall_now);
+
+        if let Some((before, _)) = clock.check(wall_now) {
+            let before = scaled_clock.at(before);
+            for session in &mut sessions {
+                session.clock_jumped(before, now - before, now, run.on_wake, &mut rng);
+            }
+        }
+
+        if let Some(watch) = &mut watch && let Some(modified) = watch.check() {
+            let modified = scaled_clock.at(modified).min(now);
+            for session in &mut sessions {
+                session.restart(modified, now, &mut rng);
+                actions.log(event_log::LogEvent::started(session.timer.range.duration), session.phase())?;
+            }
+        }
+
+        if fit_to_terminal && let Ok(size) = crossterm::terminal::size() && terminal_size != Some(size) {
+            terminal_size = Some(size);
+            let labeled = sessions.iter().any(|session| session.phase().label.is_some());
+            let (width, height) = fitted_size(run, sessions.len(), labeled, sessions[0].count_up.is_some(), size);
+            for session in &mut sessions {
+                // Sized without the stand, which goes around it
+                let stand = 2 * session.glass.decor() as usize;
+                if (session.glass.width() - stand, session.glass.height() - stand) != (width, height) {
+                    session.resize(width, height, &mut rng).map_err(|err| format!("can't resize the hourglass: {}", err))?;
                 }
-            }),
-            (Some(begin), Some(end), Some(length)) => if (*end - *begin) == *length {
-                Ok(TimeRange {
-                    start: *begin,
-                    duration: *length
-                })
+            }
+        }
+
+        let mut requests = Vec::<Request>::new();
+
+        #[cfg(unix)]
+        requests.extend(signals.pending().map(|command| Request { command, reply: None }));
+
+        #[cfg(unix)]
+        if let Some(socket) = &control_socket {
+            requests.extend(socket.pending());
+        }
+
+        if let Some(mouse) = &mouse {
+            requests.extend(mouse.pending());
+        }
+
+        #[cfg(feature = "dbus")]
+        if let Some(dbus) = &dbus {
+            requests.extend(dbus.pending());
+        }
+
+        #[cfg(feature = "notify")]
+        requests.extend(picked_notification_actions.try_iter());
+
+        if let Some(stdin_requests) = &stdin_requests {
+            requests.extend(stdin_requests.try_iter());
+        }
+
+        let mut status_requests = Vec::<Request>::new();
+        for request in requests {
+            match request.command {
+                Command::Status => {
+                    status_requests.push(request);
+                    continue;
+                },
+                Command::Quit { exit_code } => {
+                    request.respond("ok".to_string());
+                    if run.stats {
+                        drop(mouse);
+                        drop(terminal);
+                        print_stats(&sessions, frame_count, run_start.elapsed());
+                    }
+                    return Ok(ExitCode::from(exit_code));
+                },
+                command => for session in &mut sessions {
+                    session.apply(command, now, &mut actions, &mut rng)?;
+                }
+            }
+
+            request.respond("ok".to_string());
+        }
+
+        if let Some(path) = &run.save_state && last_save.is_none_or(|time| time.elapsed() >= save_interval) {
+            state_file::save(path, &sessions[0].timer, &sessions[0].glass)?;
+            last_save = Some(std::time::Instant::now());
+        }
+
+        let progresses: Vec<Progress> = sessions.iter_mut().map(|session| session.measure(now)).collect();
+
+        for request in status_requests {
+            request.respond(if let [progress] = progresses.as_slice() {
+                serde_json::to_string(progress)?
             } else {
-                Err("`length` and `begin`..`end` must define the same duration")
-            },
+                serde_json::to_string(&progresses)?
+            });
+        }
+
+        if let Some(recorder) = &mut recorder {
+            take_history(&mut sessions, &mut history);
+            recorder.frame(std::mem::take(&mut history), &progresses)?;
+        }
+
+        let frames: Vec<(&Hourglass, &Progress)> = sessions.iter().map(|session| &session.glass).zip(&progresses).collect();
+        if let Some(announcer) = &mut announcer {
+            announcer.update(&progresses.iter().collect::<Vec<_>>())?;
+        } else if !run.headless {
+            if !snapshots {
+                output::write_frame(run.output, &draw_options, &frames)?;
+            } else if last_snapshot.is_none_or(|time| time.elapsed() >= snapshot_interval) {
+                output::write_snapshot(&draw_options, &frames)?;
+                last_snapshot = Some(std::time::Instant::now());
+            }
+        }
+        if let Some(title) = &mut title {
+            title.update(&progresses.iter().collect::<Vec<_>>())?;
+        }
+        #[cfg(feature = "dbus")]
+        if let Some(dbus) = &dbus {
+            dbus.update(&progresses[0]);
+        }
+        #[cfg(unix)]
+        if let Some(systemd) = &mut systemd {
+            systemd.update(&progresses.iter().collect::<Vec<_>>())?;
+        }
+        if let Some(taskbar_progress) = &mut taskbar_progress {
+            taskbar_progress.update(&progresses[0])?;
+        }
+
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &mut metrics {
+            metrics.update(&frames, run.steps_per_frame);
+        }
+        #[cfg(feature = "serve")]
+        if let Some(web) = &web {
+            web.update(&frames)?;
+        }
+        #[cfg(feature = "serve")]
+        if let Some(websocket) = &websocket {
+            websocket.update(&frames)?;
+        }
+
+        frame_count += 1;
+        for session in &mut sessions {
+            session.advance(run.steps_per_frame, &mut rng);
+            session.fire_events(run.repeat, &mut actions)?;
+        }
+        if let Some(violation) = sessions.iter().find_map(|session| session.violation()) {
+            // The dump is written after the terminal is back to normal, so it doesn't get cleared
+            drop(mouse);
+            drop(terminal);
+            eprintln!("{}", violation);
+            return Ok(ExitCode::FAILURE);
         }
+        take_history(&mut sessions, &mut history);
+
+        if run.exit_when_done && sessions.iter().all(|session| session.is_finished()) {
+            // The log should end with how it ended up
+            if snapshots && !run.headless && !run.accessible {
+                let progresses: Vec<Progress> = sessions.iter_mut().map(|session| session.measure(now)).collect();
+                let frames: Vec<(&Hourglass, &Progress)> = sessions.iter().map(|session| &session.glass).zip(&progresses).collect();
+                output::write_snapshot(&draw_options, &frames)?;
+            }
+            drop(terminal);
+            if run.stats {
+                print_stats(&sessions, frame_count, run_start.elapsed());
+            }
+            actions.finish();
+            return Ok(ExitCode::SUCCESS);
+        }
+
+        std::thread::sleep(std::time::Duration::from_secs_f64(1.0 / run.frames_per_sec));
     }
+}
 
+/// Writes the `--stats` of each session to stderr, where it doesn't get mixed up with the frames.
+fn print_stats(sessions: &[Session], frames: u64, actual: std::time::Duration) {
+    for session in sessions {
+        let stats = &session.stats;
+        if let Some(label) = &session.phase().label && sessions.len() > 1 {
+            eprintln!("{}:", label);
+        }
+        eprintln!("duration:      {} configured, {} actual", format_time(session.total_duration()), format_time(TimeDelta::from_std(actual).unwrap_or(TimeDelta::MAX)));
+        eprintln!("steps:         {} ({:.1} per frame over {} frames)", stats.steps, stats.steps as f64 / frames.max(1) as f64, frames);
+        eprintln!("grain moves:   {}", stats.moves);
+        eprintln!("sand vs time:  {:.1}% off on average, {:.1}% at most", stats.mean_deviation() * 100.0, stats.max_deviation * 100.0);
+    }
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args = Args::parse();
+/// The preset picked on the command line, whether it's given to a subcommand or not.
+fn preset_name(matches: &clap::ArgMatches) -> Option<String> {
+    let matches = matches.subcommand().map_or(matches, |(_, matches)| matches);
+    matches.try_get_one::<String>("preset").ok().flatten().cloned()
+}
 
-    let time_range = TimeRange::try_from_args(
-        if let Some(begin_arg) = &args.begin { Some(parse_timestamp(begin_arg)?) } else { None },
-        if let Some(end_arg) = &args.end { Some(parse_timestamp(end_arg)?) } else { None },
-        if let Some(length_arg) = &args.length { Some(parse_time(length_arg)?) } else { None }
-    )?;
+/// The size to make `glasses` glasses so they fit side by side in a terminal of `(columns, rows)`.
+fn fitted_size(run: &RunArgs, glasses: usize, labeled: bool, count_up: bool, (columns, rows): (u16, u16)) -> (usize, usize) {
+    let free_rows = (rows as usize).saturating_sub(text_rows(run, labeled, count_up));
+    // Three spaces between glasses
+    let free_columns = ((columns as usize + 3) / glasses).saturating_sub(3) / if run.wide { 2 } else { 1 };
+    fit_glass(&run.glass, free_columns, free_rows)
+}
 
-    let mut glass = Hourglass::new(args.width.try_into().unwrap(), args.height.try_into().unwrap());
-    glass.fill_with_sand_from_top(args.fullness / 2.0);
-    glass.pinch();
-    glass.settle_state(&mut rand::rng());
+/// How many lines are drawn around each glass, at most.
+fn text_rows(run: &RunArgs, labeled: bool, count_up: bool) -> usize {
+    let clock_rows = match run.clock_overlay {
+        Some(ClockOverlay::Below) => 5,
+        _ => count_up as usize
+    };
+    let percent_rows = match run.show_percent {
+        true => 3 - count_up as usize,
+        false => 0
+    };
 
-    loop {
-        print!("{esc}[2J{esc}[1;1H", esc = 27 as char); // Clear and go to top left corner
-        println!("{}", glass);
+    // The last one says it's paused
+    labeled as usize + clock_rows + run.show_eta as usize + percent_rows + 1
+}
 
-        let now = chrono::Local::now().naive_local();
-        let elapsed = now - time_range.start;
+/// The biggest size that fits in `columns` and `rows` with about the same shape as asked for, or
+/// the size asked for if it already fits or no size would.
+fn fit_glass(glass_args: &GlassArgs, columns: usize, rows: usize) -> (usize, usize) {
+    let (width, height) = (glass_args.width as usize, glass_args.height as usize);
+    // The stand goes around the glass
+    let (columns, rows) = match glass_args.decor {
+        true => (columns.saturating_sub(2), rows.saturating_sub(2)),
+        false => (columns, rows)
+    };
+    if width <= columns && height <= rows {
+        return (width, height);
+    }
 
-        let time_progress: f64 = elapsed.num_milliseconds() as f64 / time_range.duration.num_milliseconds() as f64;
+    let scale = (columns as f64 / width as f64).min(rows as f64 / height as f64);
+    // Odd, and with room for the neck
+    let mut fitted_width = ((width as f64 * scale) as usize).max(glass_args.neck_width as usize + 2).max(3);
+    if fitted_width.is_multiple_of(2) {
+        fitted_width -= 1;
+    }
+    let fitted_height = ((height as f64 * scale) as usize).max(fitted_width / glass_args.aspect as usize + 1);
 
-        let top_sand = glass.count_top_sand();
-        let bottom_sand = glass.count_bottom_sand();
-        let sand_progress: f64 = if top_sand + bottom_sand != 0 {
-            bottom_sand as f64 / (top_sand + bottom_sand) as f64
-        } else {
-            0.0
+    match fitted_width <= columns && fitted_height <= rows {
+        true => (fitted_width, fitted_height),
+        false => (width, height)
+    }
+}
+
+/// Prints glasses with `progress` of the sand run down, then more and more until all of it is.
+fn render(glass_args: &GlassArgs, progress: f64, frames: u32, seed: Option<u64>, backend: RenderBackend) -> Result<(), Box<dyn std::error::Error>> {
+    use rand::{Rng, SeedableRng};
+
+    // Steps to wait for the sand to get down far enough, or to settle, before drawing it anyway
+    const MAX_STEPS: u32 = 100_000;
+
+    let mut rng = rand::rngs::SmallRng::seed_from_u64(seed.unwrap_or_else(rand::random));
+    let (mut glass, settled) = Hourglass::builder()
+        .width(glass_args.width as usize)
+        .height(glass_args.height as usize)
+        .neck_width(glass_args.neck_width as usize)
+        .shape(glass_args.shape)
+        .cell_capacity(glass_args.cell_capacity)
+        .fullness(glass_args.fullness / 2.0)
+        .avalanches(glass_args.avalanche)
+        .terminal_velocity(glass_args.terminal_velocity)
+        .decor(glass_args.decor)
+        .wall_style(glass_args.wall_style)
+        .aspect(glass_args.aspect)
+        .pinched(true)
+        .seed(rng.random())
+        .build_with_report()
+        .map_err(|err| format!("can't make the hourglass: {}", err))?;
+    let report_unsettled = |settled: Option<SettleReport>| if let Some(settled) = settled && !settled.converged {
+        eprintln!("the sand didn't come to rest in {} steps, drawing it still moving", settled.steps);
+    };
+    report_unsettled(settled);
+    let total_sand = glass.count_top_sand() + glass.count_bottom_sand();
+    let mut renderer = backend.renderer(std::io::stdout().lock());
+
+    for frame in 0..frames {
+        let target = match frames {
+            1 => progress,
+            _ => progress + (1.0 - progress) * frame as f64 / (frames - 1) as f64
         };
 
-        if sand_progress < time_progress {
-            glass.unpinch();
-        } else {
-            glass.pinch();
+        // The first frame starts with the sand where it should be, the others show it falling there
+        if frame == 0 {
+            report_unsettled(Some(glass.set_progress(target as f32, &mut rng)));
         }
 
-        //println!("elapsed: {} sand: {} time: {}", elapsed, sand_progress, time_progress);
-        //println!("begin: {} duration: {} now: {}", time_range.start.format("%H:%M:%S"), time_range.duration, now.format("%H:%M:%S"));
-
-        // TODO stop simulating until next unpinch when steady state is reached
-        // TODO catch up when behind time
-        for _ in 0..args.steps_per_frame {
-            glass.advance(&mut rand::rng());
+        // Let sand through until enough of it is down, then let what's falling land
+        glass.unpinch();
+        for _ in 0..MAX_STEPS {
+            if glass.count_bottom_sand() as f64 >= target * total_sand as f64 {
+                break;
+            }
+            glass.advance(&mut rng);
         }
+        glass.pinch();
+        report_unsettled(Some(glass.settle_with_limit(MAX_STEPS.into(), &mut rng)));
+
+        let hud = hourgals::render::Hud {
+            title: None,
+            lines: if frames > 1 { vec!["-".repeat(glass.width())] } else { Vec::new() }
+        };
+        renderer.draw(&glass, &hud)?;
+    }
+
+    Ok(())
+}
+
+/// Runs `steps` steps as fast as it can, and prints the steps and grain moves per second.
+fn bench(glass_args: &GlassArgs, steps: u32, seed: Option<u64>) -> Result<(), Box<dyn std::error::Error>> {
+    use rand::{Rng, SeedableRng};
 
-        std::thread::sleep(std::time::Duration::from_secs_f64(1.0 / args.frames_per_sec as f64));
+    let mut rng = rand::rngs::SmallRng::seed_from_u64(seed.unwrap_or_else(rand::random));
+    let (mut glass, settled) = Hourglass::builder()
+        .width(glass_args.width as usize)
+        .height(glass_args.height as usize)
+        .neck_width(glass_args.neck_width as usize)
+        .shape(glass_args.shape)
+        .cell_capacity(glass_args.cell_capacity)
+        .fullness(glass_args.fullness / 2.0)
+        .avalanches(glass_args.avalanche)
+        .terminal_velocity(glass_args.terminal_velocity)
+        .decor(glass_args.decor)
+        .wall_style(glass_args.wall_style)
+        .aspect(glass_args.aspect)
+        .seed(rng.random())
+        .build_with_report()
+        .map_err(|err| format!("can't make the hourglass: {}", err))?;
+    if let Some(settled) = settled && !settled.converged {
+        eprintln!("the sand didn't come to rest in {} steps, starting with it still moving", settled.steps);
     }
+
+    let mut moves = 0u64;
+    let started = std::time::Instant::now();
+    for _ in 0..steps {
+        let step_moves = glass.advance(&mut rng);
+        // Keeps going with sand to move instead of timing a glass where nothing happens
+        if step_moves == 0 && glass.count_top_sand() == 0 {
+            glass.flip();
+        }
+        moves += step_moves as u64;
+    }
+    let secs = started.elapsed().as_secs_f64();
+
+    let mut stdout = std::io::stdout().lock();
+    writeln!(stdout, "{} steps of a {}x{} glass in {:.3}s", steps, glass.width(), glass.height(), secs)?;
+    writeln!(stdout, "{:.0} steps/s", steps as f64 / secs)?;
+    writeln!(stdout, "{:.0} grain moves/s", moves as f64 / secs)?;
+    Ok(())
 }
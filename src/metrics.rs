@@ -0,0 +1,117 @@
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+
+use hourgals::Hourglass;
+
+use crate::output::Progress;
+
+
+/// Serves the state of the timers over HTTP in the Prometheus text format. The page is rendered
+/// once per frame, and handed out as is to whoever scrapes it.
+pub struct MetricsServer {
+    page: Arc<Mutex<String>>,
+    /// Steps simulated since `window_start`
+    window_steps: u64,
+    window_start: std::time::Instant,
+    steps_per_sec: f64
+}
+
+impl MetricsServer {
+
+    pub fn bind(address: SocketAddr) -> std::io::Result<MetricsServer> {
+        let listener = TcpListener::bind(address)?;
+        let page = Arc::new(Mutex::new(String::new()));
+
+        let served_page = Arc::clone(&page);
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let page = Arc::clone(&served_page);
+                std::thread::spawn(move || serve(stream, &page));
+            }
+        });
+
+        Ok(MetricsServer {
+            page,
+            window_steps: 0,
+            window_start: std::time::Instant::now(),
+            steps_per_sec: 0.0
+        })
+    }
+
+    /// Renders the page for this frame. `steps` is how many steps each glass was advanced by since
+    /// the last frame.
+    pub fn update(&mut self, frames: &[(&Hourglass, &Progress)], steps: u32) {
+        self.window_steps += steps as u64;
+        let window = self.window_start.elapsed();
+        if window >= std::time::Duration::from_secs(1) {
+            self.steps_per_sec = self.window_steps as f64 / window.as_secs_f64();
+            self.window_steps = 0;
+            self.window_start = std::time::Instant::now();
+        }
+
+        let mut page = String::new();
+        let mut gauge = |name: &str, help: &str, value: &dyn Fn(&Hourglass, &Progress) -> Option<f64>| {
+            let _ = writeln!(page, "# HELP {} {}\n# TYPE {} gauge", name, help, name);
+            for (i, (glass, progress)) in frames.iter().enumerate() {
+                if let Some(value) = value(glass, progress) {
+                    let _ = writeln!(page, "{}{{{}}} {}", name, labels(i, progress), value);
+                }
+            }
+        };
+
+        gauge("hourgals_remaining_seconds", "Time left until the timer is up.", &|_, progress| progress.remaining_secs);
+        gauge("hourgals_elapsed_seconds", "Time passed since the timer started, not counting pauses.", &|_, progress| Some(progress.elapsed_secs));
+        gauge("hourgals_progress_ratio", "How much of the time has passed, from 0 to 1.", &|_, progress| Some(progress.time_progress));
+        gauge("hourgals_top_sand_grains", "Grains of sand in the top half of the glass.", &|glass, _| Some(glass.count_top_sand() as f64));
+        gauge("hourgals_bottom_sand_grains", "Grains of sand in the bottom half of the glass.", &|glass, _| Some(glass.count_bottom_sand() as f64));
+        gauge("hourgals_paused", "1 if the timer is paused, otherwise 0.", &|_, progress| Some(if progress.paused { 1.0 } else { 0.0 }));
+
+        let _ = writeln!(page, "# HELP hourgals_simulation_steps_per_second Steps each glass is simulated at.");
+        let _ = writeln!(page, "# TYPE hourgals_simulation_steps_per_second gauge");
+        let _ = writeln!(page, "hourgals_simulation_steps_per_second {}", self.steps_per_sec);
+
+        *self.page.lock().unwrap() = page;
+    }
+
+}
+
+
+/// The labels telling the timers apart, like `timer="0",label="tea"`.
+fn labels(index: usize, progress: &Progress) -> String {
+    let mut labels = format!("timer=\"{}\"", index);
+    if let Some(label) = &progress.label {
+        let escaped = label.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n");
+        let _ = write!(labels, ",label=\"{}\"", escaped);
+    }
+    labels
+}
+
+/// Answers one request. Only `/metrics` is there, anything else is not found.
+fn serve(stream: TcpStream, page: &Mutex<String>) -> std::io::Result<()> {
+    let mut reader = BufReader::new(&stream);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // The headers don't matter, but have to be read before answering
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let path = request_line.split_whitespace().nth(1).unwrap_or("");
+    let (status, body) = if path == "/metrics" || path.starts_with("/metrics?") {
+        ("200 OK", page.lock().unwrap().clone())
+    } else {
+        ("404 Not Found", "not found, try /metrics\n".to_string())
+    };
+
+    let mut stream = &stream;
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status, body.len(), body
+    )?;
+    stream.flush()
+}
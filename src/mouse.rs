@@ -0,0 +1,96 @@
+use std::sync::mpsc::Receiver;
+use std::time::{Duration, Instant};
+
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
+
+use crate::control::{Command, Request};
+
+
+/// Puts the terminal in raw mode with mouse capture, and turns clicks and a few keys into commands.
+/// The terminal is put back the way it was when dropped.
+///
+/// A click toggles pause, and dragging downwards or double clicking flips the glass (the two
+/// clicks of a double click cancel each other out). Space and `f` do the same, Enter resumes, and
+/// `q` or Ctrl+C quit, since raw mode keeps Ctrl+C from sending SIGINT.
+///
+/// Without capturing the mouse, only the keys work. That's used on Windows, where there's no signal
+/// to catch, so Ctrl+C still puts the terminal back before quitting.
+pub struct MouseInput {
+    capture_mouse: bool,
+    requests: Receiver<Request>
+}
+
+impl MouseInput {
+
+    /// Clicks closer together than this make a double click
+    const DOUBLE_CLICK: Duration = Duration::from_millis(400);
+
+    pub fn new(capture_mouse: bool) -> std::io::Result<MouseInput> {
+        crossterm::terminal::enable_raw_mode()?;
+        if capture_mouse {
+            crossterm::execute!(std::io::stdout(), crossterm::event::EnableMouseCapture)?;
+        }
+
+        let (sender, requests) = std::sync::mpsc::channel();
+
+        std::thread::spawn(move || {
+            let mut pressed_at_row: Option<u16> = None;
+            let mut last_click: Option<Instant> = None;
+
+            while let Ok(event) = crossterm::event::read() {
+                let command = match event {
+                    Event::Mouse(MouseEvent { kind: MouseEventKind::Down(MouseButton::Left), row, .. }) => {
+                        pressed_at_row = Some(row);
+                        None
+                    },
+                    Event::Mouse(MouseEvent { kind: MouseEventKind::Up(MouseButton::Left), row, .. }) => match pressed_at_row.take() {
+                        Some(pressed_at_row) if row >= pressed_at_row + 2 => Some(Command::Flip),
+                        Some(_) => {
+                            let now = Instant::now();
+                            let double_click = last_click.is_some_and(|last_click| now - last_click < Self::DOUBLE_CLICK);
+                            last_click = if double_click { None } else { Some(now) };
+
+                            if double_click {
+                                // The first click already paused, this one unpauses before flipping
+                                let _ = sender.send(Request { command: Command::TogglePause, reply: None });
+                                Some(Command::Flip)
+                            } else {
+                                Some(Command::TogglePause)
+                            }
+                        },
+                        None => None
+                    },
+                    Event::Key(KeyEvent { code: KeyCode::Char('c'), modifiers, .. }) if modifiers.contains(KeyModifiers::CONTROL) => Some(Command::Quit { exit_code: 130 }),
+                    Event::Key(KeyEvent { code: KeyCode::Char('q'), .. }) => Some(Command::Quit { exit_code: 0 }),
+                    Event::Key(KeyEvent { code: KeyCode::Char(' '), .. }) => Some(Command::TogglePause),
+                    Event::Key(KeyEvent { code: KeyCode::Char('f'), .. }) => Some(Command::Flip),
+                    Event::Key(KeyEvent { code: KeyCode::Enter, .. }) => Some(Command::Resume),
+                    _ => None
+                };
+
+                if let Some(command) = command && sender.send(Request { command, reply: None }).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(MouseInput { capture_mouse, requests })
+    }
+
+    /// Requests made since the last call, without blocking.
+    pub fn pending(&self) -> impl Iterator<Item = Request> + '_ {
+        self.requests.try_iter()
+    }
+
+}
+
+impl Drop for MouseInput {
+
+    fn drop(&mut self) {
+        if self.capture_mouse {
+            let _ = crossterm::execute!(std::io::stdout(), crossterm::event::DisableMouseCapture);
+        }
+        let _ = crossterm::terminal::disable_raw_mode();
+    }
+
+}
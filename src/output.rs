@@ -0,0 +1,810 @@
+use std::io::{IsTerminal, Write};
+
+use crossterm::cursor::{Hide, MoveRight, MoveTo, Show};
+use crossterm::style::ResetColor;
+use crossterm::terminal::{Clear, ClearType};
+use hourgals::Hourglass;
+use hourgals::render::{ColorRenderer, Hud, PlainRenderer, Renderer, SandStyle};
+use unicode_width::UnicodeWidthStr;
+
+use crate::compositor::{Frame, Layer};
+use crate::events::HookPoint;
+use crate::timer::Zone;
+
+
+/// How each frame gets written to stdout.
+#[derive(clap::ValueEnum, Clone, Copy, PartialEq, Debug)]
+pub enum OutputMode {
+    /// Draw the hourglass, redrawing the whole terminal every frame.
+    Terminal,
+    /// One JSON object per line describing the progress, for other programs to consume.
+    Json,
+    /// One line of JSON per frame for a Waybar custom module (or i3blocks), with a small glyph and
+    /// the remaining time.
+    Waybar,
+    /// A short line per frame with tmux color codes, like `⧗ 12:41`. (see the `status` subcommand
+    /// for `status-right`)
+    Tmux
+}
+
+
+/// How the `render` subcommand draws the glasses.
+#[derive(clap::ValueEnum, Clone, Copy, PartialEq, Debug)]
+pub enum RenderBackend {
+    /// Text, the same as the timer draws it without any options.
+    Plain,
+    /// Text with the sand in color.
+    Color,
+    /// Braille dots, 2 by 4 cells to a character.
+    Braille,
+    /// A binary PPM image for every frame, 4 pixels per cell.
+    Ppm
+}
+
+impl RenderBackend {
+
+    /// Something that draws this way to `out`.
+    pub fn renderer<'a>(self, out: impl Write + 'a) -> Box<dyn hourgals::render::Renderer + 'a> {
+        use hourgals::render::*;
+
+        match self {
+            RenderBackend::Plain => Box::new(PlainRenderer::new(out)),
+            // The frames are far apart, so there's no telling what moved recently
+            RenderBackend::Color => Box::new(ColorRenderer::new(out, 0)),
+            RenderBackend::Braille => Box::new(BrailleRenderer::new(out)),
+            RenderBackend::Ppm => Box::new(PpmRenderer::new(out, 4))
+        }
+    }
+
+}
+
+
+/// Where the timer is at, as of the current frame.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Progress {
+    pub label: Option<String>,
+    pub elapsed_secs: f64,
+    /// `None` when counting up
+    pub remaining_secs: Option<f64>,
+    /// `None` when counting up
+    pub duration_secs: Option<f64>,
+    /// When the time is up, `None` when counting up
+    pub ends_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub time_progress: f64,
+    pub sand_progress: f64,
+    pub pinched: bool,
+    /// How open the neck is, from 0 (pinched) to 1
+    #[serde(default)]
+    pub flow: f32,
+    pub paused: bool,
+    /// How far past the end the time is, with `--overtime`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub overtime_secs: Option<f64>
+}
+
+
+/// Sets the terminal up for drawing frames, and puts it back the way it was when dropped.
+///
+/// Clearing and moving the cursor go through crossterm, which falls back to the console API on
+/// Windows consoles that don't understand escape sequences.
+pub struct TerminalGuard {
+    mode: OutputMode
+}
+
+impl TerminalGuard {
+
+    pub fn new(mode: OutputMode) -> std::io::Result<TerminalGuard> {
+        if mode == OutputMode::Terminal {
+            enable_escapes();
+            crossterm::execute!(std::io::stdout(), Hide)?;
+        }
+
+        Ok(TerminalGuard { mode })
+    }
+
+}
+
+impl Drop for TerminalGuard {
+
+    fn drop(&mut self) {
+        if self.mode == OutputMode::Terminal {
+            let _ = crossterm::execute!(std::io::stdout(), ResetColor, Show);
+        }
+    }
+
+}
+
+
+/// Whether the terminal understands escape sequences, turning them on first if it's a Windows
+/// console that can but has them off. Colors are written as escape sequences, so they need this.
+pub fn enable_escapes() -> bool {
+    #[cfg(windows)]
+    return crossterm::ansi_support::supports_ansi();
+    #[cfg(not(windows))]
+    return true;
+}
+
+
+/// How the glasses get drawn in the terminal.
+pub struct DrawOptions {
+    /// Sand that moved within this many steps is drawn brighter, fading as it gets older
+    pub fresh_sand_steps: Option<u64>,
+    /// Drawn in place of grains that just fell straight down, so streams look like they're moving
+    pub falling_glyph: Option<char>,
+    /// Draw a speck next to grains that just landed, for a frame
+    pub dust: bool,
+    /// Drawn for every cell with sand in it instead of the dots, can be two columns wide
+    pub sand_glyph: Option<String>,
+    /// Every cell takes up two columns, so they look about square
+    pub wide: bool,
+    pub background: Background,
+    pub clock_overlay: Option<ClockOverlay>,
+    /// Show how far along the time and the sand are, in percent and as a bar
+    pub show_percent: bool,
+    /// Show when the time will be up, in this time zone
+    pub eta_zone: Option<Zone>,
+    /// One line per timer instead of the glasses, even if they'd fit
+    pub compact: bool,
+    /// The glass turns yellow when it gets to this point, and red halfway from there to the end
+    pub warn_at: Option<HookPoint>,
+    /// Flash the glass a few times when the time runs out
+    pub flash: bool,
+    /// Draw the glass in red while it's in overtime
+    pub overtime_color: bool
+}
+
+
+/// Where to draw the time left in big digits.
+#[derive(clap::ValueEnum, Clone, Copy, PartialEq, Debug)]
+pub enum ClockOverlay {
+    /// On top of the bottom half of the glass.
+    Over,
+    /// Under the glass.
+    Below
+}
+
+
+/// Whether to use colors.
+#[derive(clap::ValueEnum, Clone, Copy, PartialEq, Debug)]
+pub enum ColorChoice {
+    /// When stdout is a terminal, unless `NO_COLOR` says otherwise or `CLICOLOR_FORCE` insists.
+    Auto,
+    Always,
+    Never
+}
+
+impl ColorChoice {
+
+    pub fn enabled(self) -> bool {
+        let env_set = |name: &str| std::env::var_os(name).is_some_and(|value| !value.is_empty());
+        let env_is = |name: &str, expected: &str| std::env::var_os(name).is_some_and(|value| value == expected);
+
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => if env_set("NO_COLOR") {
+                false
+            } else if env_set("CLICOLOR_FORCE") && !env_is("CLICOLOR_FORCE", "0") {
+                true
+            } else if env_is("CLICOLOR", "0") {
+                false
+            } else {
+                std::io::stdout().is_terminal() && enable_escapes()
+            }
+        }
+    }
+
+}
+
+
+/// What's drawn behind the glasses.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Background {
+    /// Whatever the terminal has.
+    Default,
+    /// Nothing at all. Only the glasses and their text are written, the rest of the screen is
+    /// skipped over, for overlays.
+    Transparent,
+    /// A solid color, given as the SGR parameters that set it, for chroma keying.
+    Color(String)
+}
+
+impl std::str::FromStr for Background {
+    type Err = String;
+
+    /// Reads `default`, `transparent`, one of the 8 basic color names, or `#rrggbb`.
+    fn from_str(text: &str) -> Result<Background, String> {
+        const NAMES: [&str; 8] = ["black", "red", "green", "yellow", "blue", "magenta", "cyan", "white"];
+
+        if text == "default" {
+            Ok(Background::Default)
+        } else if text == "transparent" {
+            Ok(Background::Transparent)
+        } else if let Some(index) = NAMES.iter().position(|&name| name == text) {
+            Ok(Background::Color(format!("{}", 40 + index)))
+        } else if let Some(hex) = text.strip_prefix('#') && hex.len() == 6 && let Ok(rgb) = u32::from_str_radix(hex, 16) {
+            Ok(Background::Color(format!("48;2;{};{};{}", rgb >> 16, (rgb >> 8) & 0xff, rgb & 0xff)))
+        } else {
+            Err(format!("invalid background `{}` (use default, transparent, a color like magenta, or #rrggbb)", text))
+        }
+    }
+}
+
+
+/// Writes one frame showing each glass with its progress.
+pub fn write_frame(mode: OutputMode, options: &DrawOptions, frames: &[(&Hourglass, &Progress)]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut stdout = std::io::stdout().lock();
+
+    match mode {
+        OutputMode::Terminal => {
+            let blocks: Vec<Vec<String>> = frames.iter().map(|(glass, progress)| glass_lines(glass, progress, options)).collect::<std::io::Result<_>>()?;
+
+            let mut lines = side_by_side(&blocks, 3);
+            // Without a size, like when it isn't a terminal or nobody set the size of the pty, the
+            // glasses are assumed to fit
+            let (columns, rows) = crossterm::terminal::size().ok().filter(|&(columns, rows)| columns > 0 && rows > 0).map_or((usize::MAX, usize::MAX), |(columns, rows)| (columns as usize, rows as usize));
+            let too_small = lines.len() > rows || lines.iter().any(|line| visible_width(line) > columns);
+            if options.compact || too_small {
+                lines = frames.iter().map(|(_, progress)| compact_line(progress, columns)).collect();
+            }
+
+            match &options.background {
+                Background::Default => {
+                    crossterm::queue!(stdout, Clear(ClearType::All), MoveTo(0, 0))?;
+                    for line in lines {
+                        // The carriage return is needed in raw mode
+                        write!(stdout, "{}\r\n", line)?;
+                    }
+                },
+                Background::Color(color) => {
+                    // Clearing fills the screen with the current background color
+                    write!(stdout, "{esc}[{}m", color, esc = 27 as char)?;
+                    crossterm::queue!(stdout, Clear(ClearType::All), MoveTo(0, 0))?;
+                    for line in lines {
+                        write!(stdout, "{}\r\n", line)?;
+                    }
+                },
+                Background::Transparent => {
+                    crossterm::queue!(stdout, MoveTo(0, 0))?;
+                    for line in lines {
+                        write_skipping_spaces(&mut stdout, &line)?;
+                        // Leftovers of the last frame past the end of the line still have to go
+                        crossterm::queue!(stdout, Clear(ClearType::UntilNewLine))?;
+                        write!(stdout, "\r\n")?;
+                    }
+                    crossterm::queue!(stdout, Clear(ClearType::FromCursorDown))?;
+                }
+            }
+            stdout.flush()?;
+        },
+        OutputMode::Json => for (_, progress) in frames {
+            writeln!(stdout, "{}", serde_json::to_string(progress)?)?;
+        },
+        OutputMode::Waybar | OutputMode::Tmux => {
+            let progresses: Vec<&Progress> = frames.iter().map(|(_, progress)| *progress).collect();
+            writeln!(stdout, "{}", status_line(mode, &progresses)?)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes the glasses like the terminal output does, but without clearing the screen, followed by a
+/// separator line. For when stdout isn't a terminal, like when it's piped into a log.
+pub fn write_snapshot(options: &DrawOptions, frames: &[(&Hourglass, &Progress)]) -> std::io::Result<()> {
+    let mut stdout = std::io::stdout().lock();
+
+    let blocks: Vec<Vec<String>> = frames.iter().map(|(glass, progress)| glass_lines(glass, progress, options)).collect::<std::io::Result<_>>()?;
+    let lines = side_by_side(&blocks, 3);
+    let width = lines.iter().map(|line| visible_width(line)).max().unwrap_or(0);
+
+    for line in lines {
+        writeln!(stdout, "{}", line.trim_end())?;
+    }
+    writeln!(stdout, "{}", "-".repeat(width))?;
+
+    stdout.flush()
+}
+
+/// Writes `line`, moving the cursor over spaces instead of writing them. The empty cells inside
+/// the glasses are no-break spaces, which do get written to clear away the sand of the last frame.
+fn write_skipping_spaces(out: &mut impl Write, line: &str) -> std::io::Result<()> {
+    let mut rest = line;
+    while !rest.is_empty() {
+        let text_len = rest.find(' ').unwrap_or(rest.len());
+        let (text, after) = rest.split_at(text_len);
+        let after_spaces = after.trim_start_matches(' ');
+
+        write!(out, "{}", text)?;
+        if after_spaces.len() < after.len() {
+            crossterm::queue!(out, MoveRight((after.len() - after_spaces.len()) as u16))?;
+        }
+        rest = after_spaces;
+    }
+
+    Ok(())
+}
+
+/// All the timers on one line, for status bars. Modes that aren't made for that get the tmux line.
+pub fn status_line(mode: OutputMode, progresses: &[&Progress]) -> Result<String, serde_json::Error> {
+    match mode {
+        OutputMode::Json => Ok(progresses.iter().map(serde_json::to_string).collect::<Result<Vec<_>, _>>()?.join("\n")),
+        OutputMode::Waybar => serde_json::to_string(&WaybarStatus::new(progresses)),
+        OutputMode::Terminal | OutputMode::Tmux => Ok(tmux_line(progresses))
+    }
+}
+
+/// Like `⧗ 12:41`, colored by whether the timer is running, paused, or done.
+fn tmux_line(progresses: &[&Progress]) -> String {
+    let parts: Vec<String> = progresses.iter().map(|progress| {
+        let color = if progress.paused {
+            "yellow"
+        } else if progress.remaining_secs == Some(0.0) {
+            "red"
+        } else {
+            "green"
+        };
+
+        format!("#[fg={}]⧗ {}#[default]", color, short_status(progress))
+    }).collect();
+
+    parts.join(" ")
+}
+
+/// The label and the time left (or elapsed, counting up), like `tea 2:41`. In overtime, it's how
+/// far over it is, like `tea +0:12 over`.
+fn short_status(progress: &Progress) -> String {
+    let time = match progress.overtime_secs {
+        Some(overtime_secs) => format!("+{} over", format_clock(overtime_secs.floor())),
+        None => format_clock(progress.remaining_secs.map_or(progress.elapsed_secs, f64::ceil))
+    };
+    match &progress.label {
+        Some(label) => format!("{} {}", label, time),
+        None => time
+    }
+}
+
+/// All the timers in plain text, like `tea 2:41, coffee 0:30 (paused)`.
+pub fn plain_status(progresses: &[&Progress]) -> String {
+    let parts: Vec<String> = progresses.iter().map(|progress| match progress.paused {
+        true => format!("{} (paused)", short_status(progress)),
+        false => short_status(progress)
+    }).collect();
+
+    parts.join(", ")
+}
+
+
+/// Keeps the terminal title showing the time left, and puts the old title back when dropped. It's
+/// written to stderr like the bell, so it doesn't get mixed into the frames.
+pub struct TitleSetter {
+    title: String
+}
+
+impl TitleSetter {
+
+    pub fn new() -> TitleSetter {
+        let mut stderr = std::io::stderr();
+        let _ = write!(stderr, "{esc}[22;0t", esc = 27 as char); // Save title
+        let _ = stderr.flush();
+
+        TitleSetter { title: String::new() }
+    }
+
+    /// Sets the title if it changed, which with the time shown in seconds happens once per second.
+    pub fn update(&mut self, progresses: &[&Progress]) -> std::io::Result<()> {
+        let title = progresses.iter().map(|progress| short_status(progress)).collect::<Vec<_>>().join(" | ");
+        if title != self.title {
+            let mut stderr = std::io::stderr();
+            write!(stderr, "{esc}]2;⧗ {}\x07", title, esc = 27 as char)?;
+            stderr.flush()?;
+            self.title = title;
+        }
+
+        Ok(())
+    }
+
+}
+
+impl Drop for TitleSetter {
+
+    fn drop(&mut self) {
+        let mut stderr = std::io::stderr();
+        let _ = write!(stderr, "{esc}[23;0t", esc = 27 as char); // Restore title
+        let _ = stderr.flush();
+    }
+
+}
+
+
+/// What a Waybar custom module with `"return-type": "json"` expects. All the timers go on one line.
+#[derive(serde::Serialize)]
+struct WaybarStatus {
+    text: String,
+    tooltip: String,
+    /// Of the first timer
+    percentage: u8,
+    /// `running`, `paused` or `done`, for styling
+    class: &'static str
+}
+
+impl WaybarStatus {
+
+    fn new(progresses: &[&Progress]) -> WaybarStatus {
+        let texts: Vec<String> = progresses.iter().map(|progress| {
+            let glyph = if progress.remaining_secs == Some(0.0) { '⌛' } else { '⏳' };
+            // Counting down, a second is shown as left until it's all gone
+            format!("{} {}", glyph, format_clock(progress.remaining_secs.map_or(progress.elapsed_secs, f64::ceil)))
+        }).collect();
+
+        let tooltips: Vec<String> = progresses.iter().map(|progress| {
+            let label = progress.label.as_deref().unwrap_or("hourgals");
+            match (progress.remaining_secs, progress.duration_secs) {
+                (Some(remaining), Some(duration)) => format!("{}: {} of {} left", label, format_clock(remaining.ceil()), format_clock(duration)),
+                _ => format!("{}: {} elapsed", label, format_clock(progress.elapsed_secs))
+            }
+        }).collect();
+
+        let class = if progresses.iter().any(|progress| progress.paused) {
+            "paused"
+        } else if progresses.iter().all(|progress| progress.remaining_secs == Some(0.0)) {
+            "done"
+        } else {
+            "running"
+        };
+
+        WaybarStatus {
+            text: texts.join("  "),
+            tooltip: tooltips.join("\n"),
+            percentage: progresses.first().map_or(0, |progress| (progress.time_progress.clamp(0.0, 1.0) * 100.0).round() as u8),
+            class
+        }
+    }
+
+}
+
+/// The lines of a glass, along with its label on top, and the elapsed time when counting up and
+/// whether it's paused below.
+fn glass_lines(glass: &Hourglass, progress: &Progress, options: &DrawOptions) -> std::io::Result<Vec<String>> {
+    let style = sand_style(options);
+    let cell_width = style.cell_width();
+    let glass_rows = glass_rows(glass, style)?;
+
+    let time = format_clock(progress.remaining_secs.map_or(progress.elapsed_secs, f64::ceil));
+    let big_digits = options.clock_overlay.map(|_| big_digits(&time));
+
+    let mut layers = vec![Layer::Rows(&glass_rows)];
+    if let (Some(ClockOverlay::Over), Some(digits)) = (options.clock_overlay, &big_digits) {
+        // Centered in the bottom half
+        let top = (glass.height() * 3 / 4).saturating_sub(digits.len() / 2);
+        layers.push(Layer::Stencil { text: digits, top, center: glass.width() * cell_width / 2 });
+    }
+    // Fresh sand keeps its shade, and whichever tint comes first wins
+    if let Some(color) = warning_color(progress, options) {
+        layers.push(Layer::Tint(color));
+    }
+    if options.overtime_color && progress.overtime_secs.is_some() {
+        layers.push(Layer::Tint(OVERTIME_COLOR));
+    }
+    if options.flash && flashing(progress) {
+        layers.push(Layer::Reverse);
+    }
+    let glass_rows = Frame::compose(&layers).to_lines();
+
+    let width = glass_rows.iter().map(|row| visible_width(row)).max().unwrap_or(0);
+    let mut lines = Vec::<String>::new();
+
+    if let Some(label) = &progress.label {
+        lines.push(format!("{:^width$}", label, width = width));
+    }
+    lines.extend(glass_rows);
+    if let (Some(ClockOverlay::Below), Some(digits)) = (options.clock_overlay, big_digits) {
+        let digits_width = digits[0].chars().count();
+        let indent = " ".repeat(width.saturating_sub(digits_width) / 2);
+        lines.extend(digits.into_iter().map(|row| format!("{}{}", indent, row)));
+    } else if progress.remaining_secs.is_none() {
+        lines.push(format!("{:^width$}", time, width = width));
+    }
+    if let Some(overtime_secs) = progress.overtime_secs {
+        lines.push(format!("{:^width$}", format!("+{} over", format_clock(overtime_secs.floor())), width = width));
+    }
+    if let (Some(zone), Some(ends_at)) = (options.eta_zone, progress.ends_at) {
+        lines.push(format!("{:^width$}", format!("done at {}", zone.format_time_of_day(ends_at)), width = width));
+    }
+    if options.show_percent {
+        let percent = |fraction: f64| (fraction.clamp(0.0, 1.0) * 100.0).floor() as u32;
+        if progress.remaining_secs.is_some() {
+            lines.push(format!("{:^width$}", format!("time {}%", percent(progress.time_progress)), width = width));
+        }
+        lines.push(format!("{:^width$}", format!("sand {}%", percent(progress.sand_progress)), width = width));
+
+        let filled = (progress.sand_progress.clamp(0.0, 1.0) * width as f64).round() as usize;
+        lines.push(format!("{}{}", "━".repeat(filled), "─".repeat(width - filled)));
+    }
+    if progress.paused {
+        lines.push(format!("{:^width$}", "paused", width = width));
+    }
+
+    Ok(lines)
+}
+
+/// How the sand gets drawn with `options`. Sand that hasn't moved lately stays in the default
+/// color, so a tint goes over it.
+fn sand_style(options: &DrawOptions) -> SandStyle {
+    SandStyle {
+        color: None,
+        fresh_steps: options.fresh_sand_steps,
+        falling_glyph: options.falling_glyph,
+        dust: options.dust,
+        sand_glyph: options.sand_glyph.clone(),
+        wide: options.wide,
+        marked_interior: options.background == Background::Transparent
+    }
+}
+
+/// The rows of the glass, drawn by the plain renderer unless `style` needs the color one.
+fn glass_rows(glass: &Hourglass, style: SandStyle) -> std::io::Result<Vec<String>> {
+    let mut drawn = Vec::<u8>::new();
+    let mut renderer: Box<dyn Renderer + '_> = match style == SandStyle::default() {
+        true => Box::new(PlainRenderer::new(&mut drawn)),
+        false => Box::new(ColorRenderer::with_style(&mut drawn, style))
+    };
+    renderer.draw(glass, &Hud::default())?;
+    drop(renderer);
+
+    Ok(String::from_utf8_lossy(&drawn).lines().map(str::to_string).collect())
+}
+
+/// Just the foreground, so a background color stays
+pub const RESET_FOREGROUND: &str = "\x1b[39m";
+
+/// The color of the glass for how close the time is to being up, if it's close enough for `--warn`.
+fn warning_color(progress: &Progress, options: &DrawOptions) -> Option<&'static str> {
+    let (warn_at, remaining_secs, duration_secs) = (options.warn_at?, progress.remaining_secs?, progress.duration_secs?);
+    let warn_secs = warn_at.remaining(chrono::TimeDelta::milliseconds((duration_secs * 1000.0) as i64)).num_milliseconds() as f64 / 1000.0;
+
+    if remaining_secs > warn_secs {
+        None
+    } else if remaining_secs > warn_secs / 2.0 {
+        Some("\x1b[33m")
+    } else {
+        Some(OVERTIME_COLOR)
+    }
+}
+
+/// Red, like the end of `--warn`
+const OVERTIME_COLOR: &str = "\x1b[31m";
+
+/// Whether the glass is drawn inverted right now, which it is every other half second for the
+/// first few seconds after the time runs out.
+fn flashing(progress: &Progress) -> bool {
+    const FLASHES: f64 = 3.0;
+
+    let Some(duration_secs) = progress.duration_secs else { return false };
+    let overtime = progress.elapsed_secs - duration_secs;
+    (0.0..FLASHES).contains(&overtime) && overtime.fract() < 0.5
+}
+
+/// Like `⧗ tea ::::::......  2:41`, with the sand that's down as a bar taking up what's left of
+/// `columns`.
+fn compact_line(progress: &Progress, columns: usize) -> String {
+    const MAX_BAR_WIDTH: usize = 40;
+
+    let glyph = if progress.remaining_secs == Some(0.0) { '⌛' } else { '⧗' };
+    let mut status = short_status(progress);
+    if progress.paused {
+        status += " (paused)";
+    }
+
+    // The glyph and two spaces around the bar
+    let bar_width = columns.saturating_sub(status.chars().count() + 4).min(MAX_BAR_WIDTH);
+    let filled = (progress.sand_progress.clamp(0.0, 1.0) * bar_width as f64).round() as usize;
+
+    format!("{} {}{} {}", glyph, ":".repeat(filled), ".".repeat(bar_width - filled), status)
+}
+
+/// `text` (digits and colons) in 5 rows tall digits made of blocks.
+fn big_digits(text: &str) -> Vec<String> {
+    const DIGITS: [[&str; 5]; 10] = [
+        ["###", "# #", "# #", "# #", "###"],
+        [" # ", "## ", " # ", " # ", "###"],
+        ["###", "  #", "###", "#  ", "###"],
+        ["###", "  #", "###", "  #", "###"],
+        ["# #", "# #", "###", "  #", "  #"],
+        ["###", "#  ", "###", "  #", "###"],
+        ["###", "#  ", "###", "# #", "###"],
+        ["###", "  #", "  #", "  #", "  #"],
+        ["###", "# #", "###", "# #", "###"],
+        ["###", "# #", "###", "  #", "###"]
+    ];
+    const COLON: [&str; 5] = [" ", "#", " ", "#", " "];
+
+    (0..5).map(|row| {
+        let parts: Vec<&str> = text.chars().map(|ch| match ch.to_digit(10) {
+            Some(digit) => DIGITS[digit as usize][row],
+            None => COLON[row]
+        }).collect();
+
+        parts.join(" ").replace('#', "█")
+    }).collect()
+}
+
+/// The number of columns the text takes up, leaving out color escape sequences.
+fn visible_width(text: &str) -> usize {
+    let mut visible = String::new();
+    let mut in_escape = false;
+    for ch in text.chars() {
+        if in_escape {
+            in_escape = !ch.is_ascii_alphabetic();
+        } else if ch == '\x1b' {
+            in_escape = true;
+        } else {
+            visible.push(ch);
+        }
+    }
+    visible.width()
+}
+
+/// Reads a glyph for `--sand-glyph`, which has to take up one or two columns.
+pub fn parse_glyph(text: &str) -> Result<String, String> {
+    match text.width() {
+        1 | 2 if !text.chars().any(char::is_control) => Ok(text.to_string()),
+        width => Err(format!("`{}` is {} columns wide, glyphs have to be 1 or 2", text, width))
+    }
+}
+
+/// Formats seconds like a clock, as M:SS or H:MM:SS.
+fn format_clock(secs: f64) -> String {
+    let secs = secs.max(0.0) as u64;
+    if secs >= 60 * 60 {
+        format!("{}:{:02}:{:02}", secs / (60 * 60), secs / 60 % 60, secs % 60)
+    } else {
+        format!("{}:{:02}", secs / 60, secs % 60)
+    }
+}
+
+/// Lays out blocks of lines in columns, `gap` spaces apart. Blocks are aligned at the bottom, so
+/// glasses line up even if only some of them have labels.
+fn side_by_side(blocks: &[Vec<String>], gap: usize) -> Vec<String> {
+    let height = blocks.iter().map(|block| block.len()).max().unwrap_or(0);
+    let widths: Vec<usize> = blocks.iter()
+        .map(|block| block.iter().map(|line| visible_width(line)).max().unwrap_or(0))
+        .collect();
+
+    (0..height).map(|row| {
+        let mut line = String::new();
+
+        for (i, block) in blocks.iter().enumerate() {
+            let text = (row + block.len()).checked_sub(height).and_then(|row| block.get(row)).map_or("", |text| text.as_str());
+            if i > 0 {
+                line += &" ".repeat(gap);
+            }
+            line += text;
+            line += &" ".repeat(widths[i] - visible_width(text));
+        }
+
+        line.trim_end().to_string()
+    }).collect()
+}
+
+
+/// Reports the progress of the first timer with OSC 9;4, which Windows Terminal and ConEmu show on
+/// the taskbar. It's cleared once the time is up, and when dropped.
+pub struct TaskbarProgress {
+    /// The state and percentage last sent
+    last: Option<(u8, u8)>
+}
+
+impl TaskbarProgress {
+
+    pub fn new() -> TaskbarProgress {
+        TaskbarProgress { last: None }
+    }
+
+    pub fn update(&mut self, progress: &Progress) -> std::io::Result<()> {
+        let percent = (progress.time_progress.clamp(0.0, 1.0) * 100.0) as u8;
+        let state = if progress.remaining_secs == Some(0.0) {
+            0 // Clear
+        } else if progress.paused {
+            4 // Warning, which shows as yellow
+        } else if progress.remaining_secs.is_none() {
+            3 // Indeterminate, since counting up has no end
+        } else {
+            1 // Normal
+        };
+
+        if self.last != Some((state, percent)) {
+            write_taskbar_progress(state, percent)?;
+            self.last = Some((state, percent));
+        }
+
+        Ok(())
+    }
+
+}
+
+impl Drop for TaskbarProgress {
+
+    fn drop(&mut self) {
+        let _ = write_taskbar_progress(0, 0);
+    }
+
+}
+
+fn write_taskbar_progress(state: u8, percent: u8) -> std::io::Result<()> {
+    let mut stderr = std::io::stderr();
+    write!(stderr, "{esc}]9;4;{};{}\x07", state, percent, esc = 27 as char)?;
+    stderr.flush()
+}
+
+
+/// Describes the timers in plain lines like `tea: 15 minutes remaining` instead of drawing them, for
+/// screen readers. A line is written whenever the time left drops past a multiple of the interval,
+/// and when a timer is paused, resumed, or done.
+pub struct Announcer {
+    interval_secs: f64,
+    /// What was last announced about each timer
+    announced: Vec<Option<(i64, bool, bool)>>
+}
+
+impl Announcer {
+
+    pub fn new(interval_secs: f64) -> Announcer {
+        Announcer { interval_secs, announced: Vec::new() }
+    }
+
+    pub fn update(&mut self, progresses: &[&Progress]) -> std::io::Result<()> {
+        self.announced.resize(progresses.len(), None);
+        let mut stdout = std::io::stdout().lock();
+
+        for (progress, announced) in progresses.iter().zip(&mut self.announced) {
+            let done = progress.remaining_secs == Some(0.0);
+            let slot = match progress.remaining_secs {
+                Some(remaining) => (remaining / self.interval_secs).ceil() as i64,
+                None => (progress.elapsed_secs / self.interval_secs).floor() as i64
+            };
+
+            if *announced == Some((slot, progress.paused, done)) {
+                continue;
+            }
+            *announced = Some((slot, progress.paused, done));
+
+            let mut line = match &progress.label {
+                Some(label) => format!("{}: ", label),
+                None => String::new()
+            };
+            if done {
+                line += "time is up";
+            } else {
+                if progress.paused {
+                    line += "paused, ";
+                }
+                line += &match progress.remaining_secs {
+                    Some(remaining) => format!("{} remaining", spoken_time(remaining.ceil())),
+                    None => format!("{} elapsed", spoken_time(progress.elapsed_secs.floor()))
+                };
+            }
+
+            writeln!(stdout, "{}", line)?;
+        }
+
+        stdout.flush()
+    }
+
+}
+
+/// Like `1 hour 5 minutes` or `30 seconds`.
+fn spoken_time(secs: f64) -> String {
+    let secs = secs.max(0.0) as u64;
+    let parts: Vec<String> = [("hour", secs / (60 * 60)), ("minute", secs / 60 % 60), ("second", secs % 60)]
+        .into_iter()
+        .filter(|&(_, count)| count > 0)
+        .map(|(unit, count)| format!("{} {}{}", count, unit, if count == 1 { "" } else { "s" }))
+        .collect();
+
+    if parts.is_empty() {
+        "0 seconds".to_string()
+    } else {
+        parts.join(" ")
+    }
+}
@@ -0,0 +1,366 @@
+use std::io::{BufRead, Write};
+use std::path::Path;
+
+use hourgals::{Hourglass, HourglassError, SettleReport, Shape, WallStyle};
+use rand::SeedableRng;
+
+use crate::output::{DrawOptions, OutputMode, Progress, TerminalGuard};
+
+
+/// Something done to a glass that changes how its sand moves from then on.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum GlassOp {
+    Flip,
+    Pinch,
+    Unpinch,
+    SetFlow(f32),
+    /// Swapped for a glass of this size with `Hourglass::resized`
+    Resize { width: usize, height: usize },
+    /// Sand moved with `Hourglass::set_progress`
+    SetProgress(f32),
+    /// This many steps in a row
+    Advance(u32)
+}
+
+impl GlassOp {
+
+    fn apply(self, glass: &mut Hourglass, rng: &mut rand::rngs::SmallRng) -> Result<(), HourglassError> {
+        match self {
+            GlassOp::Resize { width, height } => *glass = glass.resized(width, height, rng)?.0,
+            GlassOp::Flip => glass.flip(),
+            GlassOp::Pinch => glass.pinch(),
+            GlassOp::Unpinch => glass.unpinch(),
+            GlassOp::SetFlow(flow) => glass.set_flow(flow),
+            GlassOp::SetProgress(progress) => {
+                glass.set_progress(progress, rng);
+            },
+            GlassOp::Advance(steps) => for _ in 0..steps {
+                glass.advance(rng);
+            }
+        }
+        Ok(())
+    }
+
+    /// Like `a20` for 20 steps, or `f0.5` for setting the flow to 0.5.
+    fn to_compact(self) -> String {
+        match self {
+            GlassOp::Flip => "F".to_string(),
+            GlassOp::Pinch => "p".to_string(),
+            GlassOp::Unpinch => "u".to_string(),
+            GlassOp::SetFlow(flow) => format!("f{}", flow),
+            GlassOp::Resize { width, height } => format!("r{}x{}", width, height),
+            GlassOp::SetProgress(progress) => format!("s{}", progress),
+            GlassOp::Advance(steps) => format!("a{}", steps)
+        }
+    }
+
+    fn parse_compact(text: &str) -> Result<GlassOp, String> {
+        let invalid = || format!("invalid op `{}`", text);
+        let Some(kind) = text.chars().next() else {
+            return Err(invalid());
+        };
+        let rest = &text[kind.len_utf8()..];
+
+        match kind {
+            'F' if rest.is_empty() => Ok(GlassOp::Flip),
+            'p' if rest.is_empty() => Ok(GlassOp::Pinch),
+            'u' if rest.is_empty() => Ok(GlassOp::Unpinch),
+            'f' => rest.parse().map(GlassOp::SetFlow).map_err(|_| invalid()),
+            's' => rest.parse().map(GlassOp::SetProgress).map_err(|_| invalid()),
+            'a' => rest.parse().map(GlassOp::Advance).map_err(|_| invalid()),
+            'r' => match rest.split_once('x') {
+                Some((width, height)) => Ok(GlassOp::Resize {
+                    width: width.parse().map_err(|_| invalid())?,
+                    height: height.parse().map_err(|_| invalid())?
+                }),
+                None => Err(invalid())
+            },
+            _ => Err(invalid())
+        }
+    }
+
+}
+
+
+/// What a glass was built from, so a compact recording can build it again instead of saving the
+/// sand.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct GlassSpec {
+    pub width: usize,
+    pub height: usize,
+    pub neck_width: usize,
+    pub shape: Shape,
+    pub cell_capacity: u8,
+    /// As given to the builder, so half of `--fullness`
+    pub fullness: f32,
+    pub pinched: bool,
+    pub avalanches: bool,
+    pub terminal_velocity: u8,
+    pub decor: bool,
+    pub wall_style: WallStyle,
+    pub aspect: u8,
+    pub seed: u64
+}
+
+impl GlassSpec {
+
+    /// Steps the sand gets to settle in, so making a glass with a layout it keeps sliding around in
+    /// doesn't hang. It's part of the spec, as a different limit would make a different glass.
+    const SETTLE_STEP_LIMIT: u64 = 100_000;
+
+    /// Builds the glass, settled the same way the builder would, up to `SETTLE_STEP_LIMIT` steps.
+    pub fn build(&self) -> Result<(Hourglass, SettleReport), HourglassError> {
+        let mut glass = Hourglass::builder()
+            .width(self.width)
+            .height(self.height)
+            .neck_width(self.neck_width)
+            .shape(self.shape)
+            .cell_capacity(self.cell_capacity)
+            .fullness(self.fullness)
+            .pinched(self.pinched)
+            .avalanches(self.avalanches)
+            .terminal_velocity(self.terminal_velocity)
+            .decor(self.decor)
+            .wall_style(self.wall_style)
+            .aspect(self.aspect)
+            .settle(false)
+            .build()?;
+        let report = glass.settle_with_limit(Self::SETTLE_STEP_LIMIT, &mut rand::rngs::SmallRng::seed_from_u64(self.seed));
+
+        Ok((glass, report))
+    }
+
+    fn to_compact(self) -> String {
+        format!(
+            "glass width={} height={} neck={} shape={} capacity={} fullness={} pinched={} avalanches={} velocity={} decor={} walls={} aspect={} seed={}",
+            self.width, self.height, self.neck_width, self.shape, self.cell_capacity, self.fullness, self.pinched, self.avalanches,
+            self.terminal_velocity, self.decor, self.wall_style, self.aspect, self.seed
+        )
+    }
+
+    fn parse_compact(line: &str) -> Result<GlassSpec, String> {
+        let Some(fields) = line.strip_prefix("glass ") else {
+            return Err("expected a glass".to_string());
+        };
+        let fields = compact_fields(fields)?;
+        let field = |name: &str| fields.iter().find(|(key, _)| *key == name).map(|(_, value)| *value).ok_or_else(|| format!("the glass has no {}", name));
+        let invalid = |name: &str| format!("invalid {} of the glass", name);
+
+        Ok(GlassSpec {
+            width: field("width")?.parse().map_err(|_| invalid("width"))?,
+            height: field("height")?.parse().map_err(|_| invalid("height"))?,
+            neck_width: field("neck")?.parse().map_err(|_| invalid("neck"))?,
+            shape: field("shape")?.parse()?,
+            cell_capacity: field("capacity")?.parse().map_err(|_| invalid("capacity"))?,
+            fullness: field("fullness")?.parse().map_err(|_| invalid("fullness"))?,
+            pinched: field("pinched")?.parse().map_err(|_| invalid("pinched"))?,
+            // Recordings from before avalanches don't have them
+            avalanches: field("avalanches").map_or(Ok(false), |value| value.parse().map_err(|_| invalid("avalanches")))?,
+            terminal_velocity: field("velocity").map_or(Ok(1), |value| value.parse().ok().filter(|&velocity| velocity > 0).ok_or_else(|| invalid("velocity")))?,
+            decor: field("decor").map_or(Ok(false), |value| value.parse().map_err(|_| invalid("decor")))?,
+            wall_style: field("walls").map_or(Ok(WallStyle::Ascii), |value| value.parse())?,
+            aspect: field("aspect").map_or(Ok(1), |value| value.parse().ok().filter(|&aspect| aspect > 0).ok_or_else(|| invalid("aspect")))?,
+            seed: field("seed")?.parse().map_err(|_| invalid("seed"))?
+        })
+    }
+
+}
+
+/// Splits `key=value key=value` up.
+fn compact_fields(text: &str) -> Result<Vec<(&str, &str)>, String> {
+    text.split_whitespace()
+        .map(|field| field.split_once('=').ok_or_else(|| format!("expected key=value, got `{}`", field)))
+        .collect()
+}
+
+
+/// The first line of a recording: the glasses as they were before the first frame, and what the
+/// sand was moved with.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Header<G> {
+    frames_per_sec: f64,
+    seed: u64,
+    glasses: Vec<G>
+}
+
+/// Every other line: what was done to each glass (by index) since the last frame, and how the
+/// timers were doing when it was drawn.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct RecordedFrame<P> {
+    ops: Vec<(usize, GlassOp)>,
+    progresses: Vec<P>
+}
+
+
+/// How the first line of a compact recording starts, with the version of the format after it.
+///
+/// A compact recording is made of lines of text. The first is `hgr 1 fps=F seed=S glasses=N`, and
+/// the next N have what each glass was built from. After those, each line is a frame, with the ops
+/// done since the last one separated by spaces. Ops on glasses other than the first are prefixed
+/// with the index of the glass, like `1:a20`. The timers aren't saved, so it's only the sand that
+/// plays back.
+const COMPACT_MAGIC: &str = "hgr 1";
+
+/// Recordings with this extension are compact.
+const COMPACT_EXTENSION: &str = "hgr";
+
+
+/// Writes a recording as it goes, one line per frame, so it's usable even if the timer gets killed.
+pub struct Recorder {
+    file: std::fs::File,
+    compact: bool
+}
+
+impl Recorder {
+
+    /// Starts a recording of `glasses`, which will then be moved with a `SmallRng` made from `seed`.
+    /// A path ending in `.hgr` makes a compact recording, which needs `specs` to be there for
+    /// every glass.
+    pub fn create(path: &Path, frames_per_sec: f64, seed: u64, glasses: &[&Hourglass], specs: &[Option<GlassSpec>]) -> Result<Recorder, Box<dyn std::error::Error>> {
+        let compact = path.extension().is_some_and(|extension| extension == COMPACT_EXTENSION);
+        let specs = match compact {
+            true => match specs.iter().copied().collect::<Option<Vec<GlassSpec>>>() {
+                Some(specs) => specs,
+                None => return Err("a loaded glass can't be recorded into a .hgr file, the whole glass is only saved in other recordings".into())
+            },
+            false => Vec::new()
+        };
+
+        let mut file = std::fs::File::create(path)?;
+        if compact {
+            writeln!(file, "{} fps={} seed={} glasses={}", COMPACT_MAGIC, frames_per_sec, seed, specs.len())?;
+            for spec in specs {
+                writeln!(file, "{}", spec.to_compact())?;
+            }
+        } else {
+            let header = Header { frames_per_sec, seed, glasses: glasses.to_vec() };
+            writeln!(file, "{}", serde_json::to_string(&header)?)?;
+        }
+
+        Ok(Recorder { file, compact })
+    }
+
+    pub fn frame(&mut self, ops: Vec<(usize, GlassOp)>, progresses: &[Progress]) -> std::io::Result<()> {
+        if self.compact {
+            let ops: Vec<String> = ops.into_iter().map(|(index, op)| match index {
+                0 => op.to_compact(),
+                _ => format!("{}:{}", index, op.to_compact())
+            }).collect();
+            return writeln!(self.file, "{}", ops.join(" "));
+        }
+
+        let frame = RecordedFrame { ops, progresses: progresses.iter().collect() };
+        writeln!(self.file, "{}", serde_json::to_string(&frame)?)
+    }
+
+}
+
+
+/// Reads the header of a compact recording, after the first line has been read into `first`.
+fn read_compact_header(first: &str, lines: &mut impl Iterator<Item = std::io::Result<String>>) -> Result<(f64, u64, Vec<Hourglass>), Box<dyn std::error::Error>> {
+    let fields = compact_fields(&first[COMPACT_MAGIC.len()..])?;
+    let field = |name: &str| fields.iter().find(|(key, _)| *key == name).map(|(_, value)| *value).ok_or_else(|| format!("the header has no {}", name));
+
+    let frames_per_sec: f64 = field("fps")?.parse()?;
+    let seed: u64 = field("seed")?.parse()?;
+    let count: usize = field("glasses")?.parse()?;
+
+    let mut glasses = Vec::new();
+    for index in 0..count {
+        let Some(line) = lines.next() else {
+            return Err(format!("glass {} is missing", index).into());
+        };
+        let spec = GlassSpec::parse_compact(&line?).map_err(|err| format!("glass {}: {}", index, err))?;
+        glasses.push(spec.build().map_err(|err| format!("glass {}: {}", index, err))?.0);
+    }
+
+    Ok((frames_per_sec, seed, glasses))
+}
+
+fn parse_compact_frame(line: &str) -> Result<Vec<(usize, GlassOp)>, String> {
+    line.split_whitespace().map(|op| match op.split_once(':') {
+        Some((index, op)) => Ok((index.parse().map_err(|_| format!("invalid glass `{}`", index))?, GlassOp::parse_compact(op)?)),
+        None => Ok((0, GlassOp::parse_compact(op)?))
+    }).collect()
+}
+
+/// Where a glass of a compact recording is at, `elapsed_secs` into it. It's shown counting up, as
+/// the timer isn't known.
+fn compact_progress(glass: &Hourglass, elapsed_secs: f64) -> Progress {
+    let top_sand = glass.count_top_sand();
+    let bottom_sand = glass.count_bottom_sand();
+    let sand_progress = if top_sand + bottom_sand != 0 { bottom_sand as f64 / (top_sand + bottom_sand) as f64 } else { 0.0 };
+
+    Progress {
+        label: None,
+        elapsed_secs,
+        remaining_secs: None,
+        duration_secs: None,
+        ends_at: None,
+        time_progress: sand_progress,
+        sand_progress,
+        pinched: glass.pinched(),
+        flow: glass.flow(),
+        paused: false,
+        overtime_secs: None
+    }
+}
+
+
+/// Plays a recording back in the terminal, `speed` times as fast as it was made.
+pub fn replay(path: &Path, speed: f64, options: &DrawOptions) -> Result<(), Box<dyn std::error::Error>> {
+    let mut lines = std::io::BufReader::new(std::fs::File::open(path)?).lines();
+
+    let Some(first) = lines.next() else {
+        return Err(format!("{} is empty", path.display()).into());
+    };
+    let first = first?;
+    let compact = first.starts_with(COMPACT_MAGIC);
+    let (frames_per_sec, seed, mut glasses) = if compact {
+        read_compact_header(&first, &mut lines)?
+    } else {
+        let header: Header<Hourglass> = serde_json::from_str(&first)?;
+        (header.frames_per_sec, header.seed, header.glasses)
+    };
+    let mut rng = rand::rngs::SmallRng::seed_from_u64(seed);
+    let frame_time = std::time::Duration::from_secs_f64(1.0 / (frames_per_sec * speed));
+
+    #[cfg(unix)]
+    let mut signals = crate::signals::Signals::new()?;
+    let _terminal = TerminalGuard::new(OutputMode::Terminal)?;
+    #[cfg(windows)]
+    let keys = crate::mouse::MouseInput::new(false)?;
+
+    for (number, line) in lines.enumerate() {
+        #[cfg(unix)]
+        if signals.pending().next().is_some() {
+            break;
+        }
+        #[cfg(windows)]
+        if keys.pending().any(|request| matches!(request.command, crate::control::Command::Quit { .. })) {
+            break;
+        }
+
+        let line = line?;
+        let (ops, progresses) = if compact {
+            (parse_compact_frame(&line).map_err(|err| format!("frame {}: {}", number + 1, err))?, None)
+        } else {
+            let frame: RecordedFrame<Progress> = serde_json::from_str(&line).map_err(|err| format!("frame {}: {}", number + 1, err))?;
+            (frame.ops, Some(frame.progresses))
+        };
+
+        for (index, op) in ops {
+            let Some(glass) = glasses.get_mut(index) else {
+                return Err(format!("frame {}: there's no glass {}", number + 1, index).into());
+            };
+            op.apply(glass, &mut rng)?;
+        }
+
+        let progresses = progresses.unwrap_or_else(|| glasses.iter().map(|glass| compact_progress(glass, number as f64 / frames_per_sec)).collect());
+        let frames: Vec<(&Hourglass, &Progress)> = glasses.iter().zip(&progresses).collect();
+        crate::output::write_frame(OutputMode::Terminal, options, &frames)?;
+        std::thread::sleep(frame_time);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,175 @@
+use chrono::{DateTime, Datelike, TimeDelta, Utc};
+
+use crate::timer::{parse_time, Zone};
+
+
+/// When a recurring timer starts again.
+pub enum Recurrence {
+    /// This long after the last start
+    Interval(TimeDelta),
+    /// Whenever the wall clock of the zone matches
+    Cron(CronSchedule, Zone)
+}
+
+impl Recurrence {
+
+    /// Reads either a time like `parse_time`, or a cron expression read in `zone`.
+    pub fn parse(text: &str, zone: Zone) -> Result<Recurrence, String> {
+        match parse_time(text) {
+            Ok(interval) if interval > TimeDelta::zero() => Ok(Recurrence::Interval(interval)),
+            Ok(_) => Err("the interval must be longer than 0".to_string()),
+            Err(time_err) => match text.parse::<CronSchedule>() {
+                Ok(schedule) => Ok(Recurrence::Cron(schedule, zone)),
+                // Whichever it looks more like is probably what was meant
+                Err(cron_err) if text.starts_with('@') || text.split_whitespace().count() == 5 => Err(format!("invalid cron expression `{}`: {}", text, cron_err)),
+                Err(_) => Err(format!("invalid time `{}`: {}", text, time_err))
+            }
+        }
+    }
+
+    /// The first start that isn't before `after`, for a timer that last started at `last_start`.
+    /// None if there isn't one within a few years.
+    pub fn next(&self, last_start: DateTime<Utc>, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            Recurrence::Interval(interval) => {
+                // Starts that got skipped over by a long round are left out, instead of all starting at once
+                let behind = (after - last_start).num_milliseconds().max(0);
+                let intervals = (behind + interval.num_milliseconds() - 1) / interval.num_milliseconds();
+                Some(last_start + *interval * intervals.max(1) as i32)
+            },
+            Recurrence::Cron(schedule, zone) => schedule.next(after, *zone)
+        }
+    }
+
+}
+
+
+/// The five fields of a crontab line, as bit sets of the values that match.
+pub struct CronSchedule {
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    /// Sunday is 0
+    days_of_week: u64,
+    /// When both days are restricted, either matching is enough, like in cron
+    either_day: bool
+}
+
+impl CronSchedule {
+
+    /// How many days ahead to look before giving up, enough for the 29th of February to come around.
+    const SEARCH_DAYS: i64 = 366 * 8;
+
+    fn matches_day(&self, date: chrono::NaiveDate) -> bool {
+        let day_of_month = self.days_of_month & (1 << date.day()) != 0;
+        let day_of_week = self.days_of_week & (1 << date.weekday().num_days_from_sunday()) != 0;
+
+        self.months & (1 << date.month()) != 0 && match self.either_day {
+            true => day_of_month || day_of_week,
+            false => day_of_month && day_of_week
+        }
+    }
+
+    fn next(&self, after: DateTime<Utc>, zone: Zone) -> Option<DateTime<Utc>> {
+        let start_date = zone.local_time(after).date();
+
+        for day in 0..Self::SEARCH_DAYS {
+            let date = start_date + TimeDelta::days(day);
+            if !self.matches_day(date) {
+                continue;
+            }
+
+            for hour in (0..24).filter(|hour| self.hours & (1 << hour) != 0) {
+                for minute in (0..60).filter(|minute| self.minutes & (1 << minute) != 0) {
+                    // Times the clocks skip over don't happen
+                    if let Some(time) = date.and_hms_opt(hour, minute, 0) && let Ok(time) = zone.resolve(time) && time >= after {
+                        return Some(time);
+                    }
+                }
+            }
+        }
+
+        None
+    }
+
+}
+
+impl std::str::FromStr for CronSchedule {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<CronSchedule, String> {
+        let text = match text {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            _ if text.starts_with('@') => return Err(format!("unknown shorthand `{}`", text)),
+            _ => text
+        };
+
+        let fields: Vec<&str> = text.split_whitespace().collect();
+        let [minutes, hours, days_of_month, months, days_of_week] = fields[..] else {
+            return Err(format!("expected 5 fields (minute, hour, day of month, month, and day of week), got {}", fields.len()));
+        };
+
+        let mut days_of_week_set = parse_cron_field(days_of_week, 0, 7).map_err(|err| format!("day of week: {}", err))?;
+        // Both 0 and 7 are Sunday
+        if days_of_week_set & (1 << 7) != 0 {
+            days_of_week_set |= 1;
+        }
+
+        Ok(CronSchedule {
+            minutes: parse_cron_field(minutes, 0, 59).map_err(|err| format!("minute: {}", err))?,
+            hours: parse_cron_field(hours, 0, 23).map_err(|err| format!("hour: {}", err))?,
+            days_of_month: parse_cron_field(days_of_month, 1, 31).map_err(|err| format!("day of month: {}", err))?,
+            months: parse_cron_field(months, 1, 12).map_err(|err| format!("month: {}", err))?,
+            days_of_week: days_of_week_set,
+            either_day: !days_of_month.starts_with('*') && !days_of_week.starts_with('*')
+        })
+    }
+}
+
+/// Reads a comma separated list of `*`, `N`, or `N-M`, each optionally followed by `/STEP`.
+fn parse_cron_field(field: &str, min: u32, max: u32) -> Result<u64, String> {
+    let parse_value = |text: &str| match text.parse::<u32>() {
+        Ok(value) if (min..=max).contains(&value) => Ok(value),
+        Ok(value) => Err(format!("{} is not between {} and {}", value, min, max)),
+        Err(_) => Err(format!("`{}` is not a number", text))
+    };
+
+    let mut set = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => match step.parse::<u32>() {
+                Ok(step) if step > 0 => (range, step),
+                _ => return Err(format!("invalid step `{}`", step))
+            },
+            None => (part, 1)
+        };
+
+        let (first, last) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((first, last)) => (parse_value(first)?, parse_value(last)?),
+                // Like `5/10`, which is from 5 to the end
+                None if step > 1 => (parse_value(range)?, max),
+                None => {
+                    let value = parse_value(range)?;
+                    (value, value)
+                }
+            }
+        };
+
+        if first > last {
+            return Err(format!("range {}-{} is backwards", first, last));
+        }
+
+        for value in (first..=last).step_by(step as usize) {
+            set |= 1 << value;
+        }
+    }
+
+    Ok(set)
+}
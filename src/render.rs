@@ -0,0 +1,331 @@
+//! Ways of drawing a glass, all behind [`Renderer`], so a new one doesn't have to touch the others.
+//!
+//! ```
+//! use hourgals::Hourglass;
+//! use hourgals::render::{Hud, PlainRenderer, Renderer};
+//!
+//! let mut glass = Hourglass::new(7, 12);
+//! glass.fill_with_sand_from_top(0.5);
+//! let mut renderer = PlainRenderer::new(Vec::new());
+//! renderer.draw(&glass, &Hud { title: Some("tea".to_string()), lines: vec!["3:00".to_string()] })?;
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use std::collections::HashSet;
+use std::io::Write;
+
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+use crate::{CellView, Hourglass, LayoutCell};
+
+
+/// The text drawn with a glass.
+#[derive(Clone, Default, Debug)]
+pub struct Hud {
+    /// Centered above the glass
+    pub title: Option<String>,
+    /// Centered below the glass, one per line
+    pub lines: Vec<String>
+}
+
+
+/// Draws a frame of a glass every time it's called, wherever it was made to draw to.
+pub trait Renderer {
+    fn draw(&mut self, glass: &Hourglass, hud: &Hud) -> std::io::Result<()>;
+}
+
+/// Writes the title, `rows`, and the other lines of the HUD, with the text centered over the rows
+/// and the ends of the lines trimmed.
+fn write_text(out: &mut impl Write, rows: &[String], width: usize, hud: &Hud) -> std::io::Result<()> {
+    if let Some(title) = &hud.title {
+        writeln!(out, "{}", format!("{:^width$}", title, width = width).trim_end())?;
+    }
+    for row in rows {
+        writeln!(out, "{}", row.trim_end())?;
+    }
+    for line in &hud.lines {
+        writeln!(out, "{}", format!("{:^width$}", line, width = width).trim_end())?;
+    }
+
+    out.flush()
+}
+
+
+/// The same text as the glass's `Display`, with the HUD around it.
+pub struct PlainRenderer<W: Write> {
+    out: W
+}
+
+impl<W: Write> PlainRenderer<W> {
+
+    pub fn new(out: W) -> PlainRenderer<W> {
+        PlainRenderer { out }
+    }
+
+    /// What it was drawing to.
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+}
+
+impl<W: Write> Renderer for PlainRenderer<W> {
+
+    fn draw(&mut self, glass: &Hourglass, hud: &Hud) -> std::io::Result<()> {
+        write_text(&mut self.out, &glass.to_lines(), glass.width(), hud)
+    }
+
+}
+
+
+/// How [`ColorRenderer`] draws the sand.
+#[derive(Clone, PartialEq, Default, Debug)]
+pub struct SandStyle {
+    /// The escape sequence setting the color of sand that hasn't moved lately, or the default color
+    /// if None
+    pub color: Option<&'static str>,
+    /// Sand that moved within this many steps is drawn brighter, fading as it gets older
+    pub fresh_steps: Option<u64>,
+    /// Drawn in place of grains that just fell straight down, so streams look like they're moving
+    pub falling_glyph: Option<char>,
+    /// Draw a speck next to grains that just landed, for a step
+    pub dust: bool,
+    /// Drawn for every cell with sand in it instead of the dots, can be two columns wide
+    pub sand_glyph: Option<String>,
+    /// Every cell takes up two columns, so they look about square
+    pub wide: bool,
+    /// The empty cells inside the glass are no-break spaces, to tell them from the outside
+    pub marked_interior: bool
+}
+
+impl SandStyle {
+
+    /// How many columns each cell of the glass takes up, enough for the widest glyph.
+    pub fn cell_width(&self) -> usize {
+        let sand_width = self.sand_glyph.as_deref().map_or(1, UnicodeWidthStr::width);
+        let falling_width = self.falling_glyph.and_then(UnicodeWidthChar::width).unwrap_or(1);
+        let least = if self.wide { 2 } else { 1 };
+        sand_width.max(falling_width).max(least)
+    }
+
+}
+
+
+/// Like [`PlainRenderer`], with ANSI colors and the glyphs of a [`SandStyle`]. Sand that moved
+/// recently is white when it's freshest, then bright yellow, then yellow. When a glyph is wider
+/// than a column, the other cells are padded to be as wide.
+pub struct ColorRenderer<W: Write> {
+    out: W,
+    style: SandStyle
+}
+
+impl<W: Write> ColorRenderer<W> {
+
+    const SAND: &str = "\x1b[33m";
+    const SHADES: [&str; 3] = ["\x1b[97m", "\x1b[93m", "\x1b[33m"];
+    const RESET: &str = "\x1b[39m";
+    /// Drawn next to where a grain lands, with `dust`
+    const DUST_GLYPH: char = '\u{2d9}';
+
+    /// Yellow sand, where what moved within `fresh_steps` steps is drawn brighter. 0 turns that off.
+    pub fn new(out: W, fresh_steps: u64) -> ColorRenderer<W> {
+        ColorRenderer::with_style(out, SandStyle {
+            color: Some(Self::SAND),
+            fresh_steps: (fresh_steps > 0).then_some(fresh_steps),
+            ..SandStyle::default()
+        })
+    }
+
+    pub fn with_style(out: W, style: SandStyle) -> ColorRenderer<W> {
+        ColorRenderer { out, style }
+    }
+
+    /// What it was drawing to.
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    /// The rows of the glass, without the HUD.
+    pub fn rows(&self, glass: &Hourglass) -> Vec<String> {
+        let style = &self.style;
+        let interior: HashSet<(usize, usize)> = match style.marked_interior {
+            true => glass.interior_positions().iter().copied().collect(),
+            false => HashSet::new()
+        };
+        let specks = match style.dust {
+            true => dust_specks(glass),
+            false => HashSet::new()
+        };
+        let cell_width = style.cell_width();
+
+        glass.to_lines().iter().enumerate().map(|(y, text)| {
+            let mut row = String::new();
+            let mut color: Option<&str> = None;
+            for (x, ch) in text.chars().enumerate() {
+                let wall = matches!(glass.layout()[(x, y)], LayoutCell::Wall(_) | LayoutCell::Frame(_));
+                let (glyph, fill) = match (style.falling_glyph, &style.sand_glyph) {
+                    (Some(glyph), _) if glass.is_falling((x, y)) => (glyph.to_string(), ' '),
+                    (_, Some(glyph)) if !wall && glass.sand()[(x, y)] > 0 => (glyph.clone(), ' '),
+                    _ if specks.contains(&(x, y)) => (Self::DUST_GLYPH.to_string(), ' '),
+                    _ if ch == ' ' && interior.contains(&(x, y)) => ('\u{a0}'.to_string(), '\u{a0}'),
+                    _ if style.wide => (glass.cell_glyphs(x, y, cell_width).unwrap_or_default(), ' '),
+                    // Walls going across stay unbroken
+                    _ if wall && matches!(ch, '=' | '_' | '#' | '\u{2500}' | '\u{2550}') => (ch.to_string(), ch),
+                    _ => (ch.to_string(), ' ')
+                };
+
+                let padding: String = std::iter::repeat_n(fill, cell_width.saturating_sub(glyph.width())).collect();
+                // Walls on the right are padded on their outside too, so the glass stays symmetric
+                let cell = match wall && x > glass.width() / 2 {
+                    true => padding + &glyph,
+                    false => glyph + &padding
+                };
+
+                let wanted = match (glass.sand_age((x, y)), style.fresh_steps) {
+                    (Some(age), Some(fresh_steps)) if age < fresh_steps => Some(Self::SHADES[(age * Self::SHADES.len() as u64 / fresh_steps) as usize]),
+                    _ if !wall && glass.sand()[(x, y)] > 0 => style.color,
+                    _ => None
+                };
+                // Only where it changes, spaces don't need one
+                if wanted != color && !cell.trim().is_empty() {
+                    row += wanted.unwrap_or(Self::RESET);
+                    color = wanted;
+                }
+                row += &cell;
+            }
+            if color.is_some() {
+                row += Self::RESET;
+            }
+            row
+        }).collect()
+    }
+
+}
+
+impl<W: Write> Renderer for ColorRenderer<W> {
+
+    fn draw(&mut self, glass: &Hourglass, hud: &Hud) -> std::io::Result<()> {
+        let rows = self.rows(glass);
+        write_text(&mut self.out, &rows, glass.width() * self.style.cell_width(), hud)
+    }
+
+}
+
+/// Where to draw dust for the grains that landed in the last step: an empty cell inside the glass
+/// beside each of them. Which side is tried first goes back and forth, so it doesn't lean one way.
+fn dust_specks(glass: &Hourglass) -> HashSet<(usize, usize)> {
+    let interior: HashSet<(usize, usize)> = glass.interior_positions().iter().copied().collect();
+    let is_free = |pos: &(usize, usize)| interior.contains(pos) && glass.sand()[*pos] == 0;
+
+    glass.landings().filter_map(|(x, y)| {
+        let left = (x.wrapping_sub(1), y);
+        let right = (x + 1, y);
+        let sides = match (x as u64 + glass.steps()) % 2 {
+            0 => [left, right],
+            _ => [right, left]
+        };
+        sides.into_iter().find(is_free)
+    }).collect()
+}
+
+
+/// Packs 2 by 4 cells into every braille character, with a dot for each one that has sand or a
+/// wall in it, for a glass that takes up an eighth of the room with all its detail.
+pub struct BrailleRenderer<W: Write> {
+    out: W
+}
+
+impl<W: Write> BrailleRenderer<W> {
+
+    pub fn new(out: W) -> BrailleRenderer<W> {
+        BrailleRenderer { out }
+    }
+
+    /// What it was drawing to.
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+}
+
+impl<W: Write> Renderer for BrailleRenderer<W> {
+
+    fn draw(&mut self, glass: &Hourglass, hud: &Hud) -> std::io::Result<()> {
+        // The bit of each dot, by row and then column within the character
+        const DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+        let columns = glass.width().div_ceil(2);
+        let rows: Vec<String> = (0..glass.height().div_ceil(4)).map(|row| {
+            (0..columns).map(|column| {
+                let mut bits = 0;
+                for (dy, dots) in DOTS.iter().enumerate() {
+                    for (dx, bit) in dots.iter().enumerate() {
+                        match glass.cell(column * 2 + dx, row * 4 + dy) {
+                            Some(CellView::Wall(_)) => bits |= bit,
+                            Some(CellView::Sand(sand)) if sand > 0 => bits |= bit,
+                            _ => ()
+                        }
+                    }
+                }
+                char::from_u32(0x2800 + bits).unwrap_or(' ')
+            }).collect()
+        }).collect();
+
+        write_text(&mut self.out, &rows, columns, hud)
+    }
+
+}
+
+
+/// Writes every frame as a binary PPM image, for pictures of a glass bigger than text can make.
+/// Images written one after the other can be piped into ffmpeg with `-f image2pipe`. There's no
+/// font to draw the HUD with, so it's left out.
+pub struct PpmRenderer<W: Write> {
+    out: W,
+    scale: usize
+}
+
+impl<W: Write> PpmRenderer<W> {
+
+    const WALL: [u8; 3] = [150, 150, 160];
+    const SAND: [u8; 3] = [230, 190, 90];
+
+    /// Every cell is a square `scale` pixels across, at least 1.
+    pub fn new(out: W, scale: usize) -> PpmRenderer<W> {
+        PpmRenderer { out, scale: scale.max(1) }
+    }
+
+    /// What it was drawing to.
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+}
+
+impl<W: Write> Renderer for PpmRenderer<W> {
+
+    fn draw(&mut self, glass: &Hourglass, _hud: &Hud) -> std::io::Result<()> {
+        let (width, height) = (glass.width() * self.scale, glass.height() * self.scale);
+        let mut pixels = Vec::<u8>::with_capacity(width * height * 3);
+
+        for y in 0..glass.height() {
+            let row: Vec<u8> = (0..glass.width()).flat_map(|x| {
+                let color = match glass.cell(x, y) {
+                    Some(CellView::Wall(_)) => Self::WALL,
+                    // Fuller cells are brighter
+                    Some(CellView::Sand(sand)) => Self::SAND.map(|channel| (channel as usize * sand as usize / glass.cell_capacity() as usize) as u8),
+                    None => [0; 3]
+                };
+                std::iter::repeat_n(color, self.scale).flatten()
+            }).collect();
+            for _ in 0..self.scale {
+                pixels.extend_from_slice(&row);
+            }
+        }
+
+        write!(self.out, "P6\n{} {}\n255\n", width, height)?;
+        self.out.write_all(&pixels)?;
+        self.out.flush()
+    }
+
+}
@@ -0,0 +1,558 @@
+use chrono::{DateTime, TimeDelta, Utc};
+use hourgals::{CellView, Hourglass, InvariantViolation, SettleReport};
+
+use crate::control::Command;
+use crate::event_log::LogEvent;
+use crate::events::{EventActions, HookPoint, HookSchedule, TimerEvent, WarningSchedule};
+use crate::output::Progress;
+use crate::recording::GlassOp;
+use crate::recurrence::Recurrence;
+use crate::timer::{Phase, TimeRange, Timer};
+
+
+/// What to do when the clock jumps, as it does after the system is woken up from suspend.
+#[derive(clap::ValueEnum, Clone, Copy, PartialEq, Debug)]
+pub enum WakeAction {
+    /// Keep going by the clock, and let the sand rush down until it's caught up.
+    CatchUp,
+    /// Pause at the time before the jump.
+    Pause,
+    /// Leave the time that was jumped over out, making the range end later.
+    Extend
+}
+
+
+/// What decides how far the neck is open.
+#[derive(clap::ValueEnum, Clone, Copy, PartialEq, Debug)]
+pub enum Controller {
+    /// Open or closed, depending on whether the sand is behind the time.
+    Pinch,
+    /// Open more the further behind the sand is.
+    Proportional,
+    /// Like proportional, but opened further the longer the sand's been behind, so it doesn't lag
+    /// on long timers. (a PID controller without the derivative, the sand is too noisy for one)
+    Pid
+}
+
+
+/// An hourglass, and the phases of time it's keeping track of.
+pub struct Session {
+    pub timer: Timer,
+    pub glass: Hourglass,
+    /// Counts up with no end instead, feeding the sand so half of it is down after this long
+    pub count_up: Option<TimeDelta>,
+    /// Whether the sand moves at all. Without it, phases are over as soon as their time is up.
+    pub simulate: bool,
+    /// Starts the phases over when this says once the last one is over, instead of finishing
+    pub recurrence: Option<Recurrence>,
+    /// Counted up since the start, for `--stats`
+    pub stats: RunStats,
+    /// Keeps track of how far past the end the last phase is, instead of just being done
+    pub overtime: bool,
+    pub controller: Controller,
+    /// Lets the neck clog now and then, with the sand coming down in a burst once it's free
+    pub jams: bool,
+    /// Checks the sand after every step, and stops simulating once something's wrong with it
+    pub check_invariants: bool,
+    /// What was wrong with the sand, with a dump of the glass
+    violation: Option<String>,
+    /// What was done to the glass since the last `take_history`, when it's being recorded
+    pub history: Option<Vec<GlassOp>>,
+    phases: Vec<Phase>,
+    phase_index: usize,
+    warnings: WarningSchedule,
+    hooks: HookSchedule,
+    /// Whether the time of the current phase ran out
+    completed: bool,
+    /// Whether the last phase is over and the sand is done
+    finished: bool,
+    inactive_steps: u32,
+    /// As of the last `measure`
+    elapsed: TimeDelta,
+    /// Grains behind over time since the flip, in grain seconds, for the PID controller
+    integral: f64,
+    /// When `integral` was last added to
+    integrated_until: Option<TimeDelta>,
+    /// The elapsed time the next jam starts at and the one it clears at, with `jams`
+    jam: Option<(TimeDelta, TimeDelta)>
+}
+
+impl Session {
+
+    /// How many grains the sand has to be behind the time for the neck to be opened all the way
+    const FULL_FLOW_GRAINS_BEHIND: f64 = 3.0;
+
+    /// Flow per grain behind, and per grain second of having been behind
+    const PID_PROPORTIONAL_GAIN: f64 = 1.0 / Self::FULL_FLOW_GRAINS_BEHIND;
+    const PID_INTEGRAL_GAIN: f64 = 0.1;
+
+    /// Seconds from one jam to the next, and how long one lasts
+    const JAM_GAP_SECS: std::ops::Range<i64> = 20..90;
+    const JAM_LENGTH_MILLIS: std::ops::Range<i64> = 1000..4000;
+    /// How much of the time is left to jam in, so the sand can still make up for it before the end
+    const JAM_UNTIL_PROGRESS: f64 = 0.9;
+
+    /// Starts the first phase with `timer`. There must be at least one phase.
+    pub fn new(timer: Timer, glass: Hourglass, phases: Vec<Phase>, warnings: Vec<TimeDelta>, hooks: Vec<HookPoint>) -> Session {
+        assert!(!phases.is_empty(), "Session must have phases");
+
+        let warnings = WarningSchedule::new(warnings, timer.range.duration);
+        let hooks = HookSchedule::new(hooks, timer.range.duration);
+
+        Session {
+            timer,
+            glass,
+            count_up: None,
+            simulate: true,
+            recurrence: None,
+            overtime: false,
+            stats: RunStats::default(),
+            controller: Controller::Proportional,
+            jams: false,
+            check_invariants: false,
+            violation: None,
+            history: None,
+            phases,
+            phase_index: 0,
+            warnings,
+            hooks,
+            completed: false,
+            finished: false,
+            inactive_steps: 0,
+            elapsed: TimeDelta::zero(),
+            integral: 0.0,
+            integrated_until: None,
+            jam: None
+        }
+    }
+
+    pub fn phase(&self) -> &Phase {
+        &self.phases[self.phase_index]
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// What went wrong with the sand, if `check_invariants` found something.
+    pub fn violation(&self) -> Option<&str> {
+        self.violation.as_deref()
+    }
+
+
+    pub fn apply(&mut self, command: Command, now: DateTime<Utc>, actions: &mut EventActions, rng: &mut impl rand::Rng) -> Result<(), Box<dyn std::error::Error>> {
+        match command {
+            Command::Pause | Command::Resume | Command::TogglePause => {
+                let was_paused = self.timer.is_paused();
+                match command {
+                    Command::Pause => self.timer.pause(now),
+                    Command::Resume => self.timer.resume(now),
+                    _ => self.timer.toggle_pause(now)
+                }
+
+                match (was_paused, self.timer.is_paused()) {
+                    (false, true) => actions.log(LogEvent::Paused, self.phase())?,
+                    (true, false) => actions.log(LogEvent::Resumed, self.phase())?,
+                    _ => ()
+                }
+            },
+            Command::Flip => {
+                self.flip_glass();
+                self.timer.flip(now);
+                self.inactive_steps = 0;
+                actions.handle(TimerEvent::Flipped, self.phase())?;
+            },
+            Command::AddTime(time) => {
+                self.timer.add(time);
+                actions.log(LogEvent::time_added(time), self.phase())?;
+            },
+            Command::Snooze(time) => {
+                // Overtime since the time ran out doesn't count
+                let overtime = (self.timer.elapsed(now) - self.timer.range.duration).max(TimeDelta::zero());
+                self.timer.add(overtime + time);
+                actions.log(LogEvent::time_added(overtime + time), self.phase())?;
+                self.elapsed = self.timer.elapsed(now);
+                self.set_progress(self.time_progress().clamp(0.0, 1.0) as f32, rng);
+            },
+            Command::Status | Command::Quit { .. } => ()
+        }
+
+        // Flipping or adding time can take the timer from done to not done
+        let remaining = self.timer.range.duration - self.timer.elapsed(now);
+        if remaining > TimeDelta::zero() {
+            self.completed = false;
+            self.finished = false;
+        }
+        self.warnings.reset(remaining);
+        self.hooks.reset(remaining, self.timer.range.duration);
+
+        Ok(())
+    }
+
+    /// Reacts to the clock jumping from `before` by `jump`, leaving `now` as the current time.
+    pub fn clock_jumped(&mut self, before: DateTime<Utc>, jump: TimeDelta, now: DateTime<Utc>, action: WakeAction, rng: &mut impl rand::Rng) {
+        match action {
+            WakeAction::CatchUp => if self.simulate {
+                self.catch_up(now, rng);
+            },
+            WakeAction::Pause => self.timer.pause(before),
+            WakeAction::Extend => self.timer.shift(jump)
+        }
+    }
+
+    /// Runs the simulation without waiting between steps until the sand is down as far as the
+    /// time, or it stops moving.
+    fn catch_up(&mut self, now: DateTime<Utc>, rng: &mut impl rand::Rng) {
+        const MAX_STEPS: u32 = 100_000;
+
+        self.elapsed = self.timer.elapsed(now);
+        self.set_flow(1.0);
+        for _ in 0..MAX_STEPS {
+            if self.sand_progress() >= self.time_progress() || self.step(rng) == 0 {
+                break;
+            }
+        }
+    }
+
+    /// Swaps the glass for one of a different size, with the sand as far down as it was.
+    pub fn resize(&mut self, width: usize, height: usize, rng: &mut impl rand::Rng) -> Result<(), hourgals::HourglassError> {
+        let (glass, settled) = self.glass.resized(width, height, rng)?;
+        self.glass = glass;
+        report_unsettled(settled);
+        self.inactive_steps = 0;
+        self.reset_controller();
+        if let Some(history) = &mut self.history {
+            history.push(GlassOp::Resize { width, height });
+        }
+
+        Ok(())
+    }
+
+    /// Moves the sand down as far as the time is, for timers that started before their glass did.
+    pub fn skip_to_time(&mut self, now: DateTime<Utc>, rng: &mut impl rand::Rng) {
+        self.elapsed = self.timer.elapsed(now);
+
+        let progress = self.time_progress();
+        if progress > 0.0 {
+            self.set_progress(progress.min(1.0) as f32, rng);
+        }
+    }
+
+    /// Starts the first phase over at `start`, with the sand as far down as the time is by `now`.
+    pub fn restart(&mut self, start: DateTime<Utc>, now: DateTime<Utc>, rng: &mut impl rand::Rng) {
+        self.phase_index = 0;
+        self.timer = Timer::new(TimeRange { start, duration: self.phases[0].duration });
+        self.completed = false;
+        self.finished = false;
+        self.warnings.reset(self.timer.range.duration);
+        self.hooks.reset(self.timer.range.duration, self.timer.range.duration);
+
+        self.elapsed = self.timer.elapsed(now);
+        self.set_progress(self.time_progress().clamp(0.0, 1.0) as f32, rng);
+    }
+
+    /// Works out the progress as of `now`, and pinches or unpinches the glass to keep the sand up with the time.
+    pub fn measure(&mut self, now: DateTime<Utc>) -> Progress {
+        self.elapsed = self.timer.elapsed(now);
+
+        let time_progress = self.time_progress();
+        let sand_progress = self.sand_progress();
+        self.stats.add_deviation((sand_progress - time_progress.min(1.0)).abs());
+
+        let total_sand = self.glass.count_top_sand() + self.glass.count_bottom_sand();
+        let grains_behind = (time_progress - sand_progress) * total_sand as f64;
+        // Held shut while jammed, the controller then opens it up to make up for it
+        let jammed = self.is_jammed();
+        let flow = match self.controller {
+            _ if jammed => 0.0,
+            Controller::Pinch => if grains_behind > 0.0 { 1.0 } else { 0.0 },
+            // Fully open when it's a few grains behind, and more and more closed as it catches up
+            Controller::Proportional => grains_behind / Self::FULL_FLOW_GRAINS_BEHIND,
+            Controller::Pid => self.pid_flow(grains_behind)
+        };
+        self.set_flow(flow as f32);
+
+        Progress {
+            label: self.phase().label.clone(),
+            elapsed_secs: self.elapsed.num_milliseconds() as f64 / 1000.0,
+            remaining_secs: self.count_up.is_none().then(|| ((self.timer.range.duration - self.elapsed).num_milliseconds() as f64 / 1000.0).max(0.0)),
+            duration_secs: self.count_up.is_none().then(|| self.timer.range.duration.num_milliseconds() as f64 / 1000.0),
+            // Moves later while paused
+            ends_at: self.count_up.is_none().then(|| now + (self.timer.range.duration - self.elapsed)),
+            time_progress,
+            sand_progress,
+            pinched: self.glass.pinched(),
+            flow: self.glass.flow(),
+            paused: self.timer.is_paused(),
+            overtime_secs: self.overtime_secs()
+        }
+    }
+
+    fn overtime_secs(&self) -> Option<f64> {
+        let last_phase = self.phase_index + 1 == self.phases.len();
+        let overtime = self.elapsed - self.timer.range.duration;
+        (self.overtime && last_phase && self.count_up.is_none() && overtime > TimeDelta::zero()).then(|| overtime.num_milliseconds() as f64 / 1000.0)
+    }
+
+    /// Whether the neck is clogged right now, clearing the jam once it's over.
+    fn is_jammed(&mut self) -> bool {
+        let Some((from, until)) = self.jam else {
+            return false;
+        };
+        if self.elapsed >= until {
+            self.reset_controller();
+            return false;
+        }
+
+        self.elapsed >= from && (self.count_up.is_some() || self.time_progress() < Self::JAM_UNTIL_PROGRESS)
+    }
+
+    fn pid_flow(&mut self, grains_behind: f64) -> f64 {
+        if let Some(until) = self.integrated_until {
+            let secs = (self.elapsed - until).num_milliseconds() as f64 / 1000.0;
+            // Only as much as can make a difference, or it takes forever to come back from being far behind
+            let limit = 1.0 / Self::PID_INTEGRAL_GAIN;
+            self.integral = (self.integral + grains_behind * secs.max(0.0)).clamp(-limit, limit);
+        }
+        self.integrated_until = Some(self.elapsed);
+
+        Self::PID_PROPORTIONAL_GAIN * grains_behind + Self::PID_INTEGRAL_GAIN * self.integral
+    }
+
+    /// Starts the PID controller over, for when what it learned about the flow doesn't hold anymore.
+    /// The next jam gets picked again too, as the time it was at may not come around anymore.
+    fn reset_controller(&mut self) {
+        self.integral = 0.0;
+        self.integrated_until = None;
+        self.jam = None;
+    }
+
+    fn sand_progress(&self) -> f64 {
+        let top_sand = self.glass.count_top_sand();
+        let bottom_sand = self.glass.count_bottom_sand();
+        if top_sand + bottom_sand != 0 {
+            bottom_sand as f64 / (top_sand + bottom_sand) as f64
+        } else {
+            0.0
+        }
+    }
+
+    /// How much of the sand should be down by now. Counting up approaches but never reaches 1.
+    fn time_progress(&self) -> f64 {
+        let elapsed = self.elapsed.num_milliseconds() as f64;
+        match self.count_up {
+            Some(half_at) => {
+                let elapsed = elapsed.max(0.0);
+                elapsed / (elapsed + half_at.num_milliseconds() as f64)
+            },
+            None => elapsed / self.timer.range.duration.num_milliseconds() as f64
+        }
+    }
+
+    pub fn advance(&mut self, steps: u32, rng: &mut impl rand::Rng) {
+        if !self.simulate || self.violation.is_some() {
+            return;
+        }
+
+        if self.jams && self.jam.is_none() {
+            let from = self.elapsed + TimeDelta::seconds(rng.random_range(Self::JAM_GAP_SECS));
+            self.jam = Some((from, from + TimeDelta::milliseconds(rng.random_range(Self::JAM_LENGTH_MILLIS))));
+        }
+
+        // TODO stop simulating until next unpinch when steady state is reached
+        // TODO catch up when behind time
+        for _ in 0..steps {
+            let moves = self.step(rng);
+            self.stats.steps += 1;
+            self.stats.moves += moves as u64;
+            if moves == 0 {
+                self.inactive_steps = self.inactive_steps.saturating_add(1);
+            } else {
+                self.inactive_steps = 0;
+            }
+        }
+    }
+
+    /// How long all the phases are together.
+    pub fn total_duration(&self) -> TimeDelta {
+        self.phases.iter().map(|phase| phase.duration).sum()
+    }
+
+    /// What was done to the glass since the last call, if it's being recorded.
+    pub fn take_history(&mut self) -> Vec<GlassOp> {
+        self.history.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    fn flip_glass(&mut self) {
+        self.glass.flip();
+        self.reset_controller();
+        if let Some(history) = &mut self.history {
+            history.push(GlassOp::Flip);
+        }
+    }
+
+    fn set_progress(&mut self, progress: f32, rng: &mut impl rand::Rng) {
+        report_unsettled(self.glass.set_progress(progress, rng));
+        self.inactive_steps = 0;
+        self.reset_controller();
+        if let Some(history) = &mut self.history {
+            history.push(GlassOp::SetProgress(progress));
+        }
+    }
+
+    /// Only recorded when it changes, as it stays the same for most frames.
+    fn set_flow(&mut self, flow: f32) {
+        let before = self.glass.flow();
+        self.glass.set_flow(flow);
+
+        if let Some(history) = &mut self.history && self.glass.flow() != before {
+            history.push(GlassOp::SetFlow(self.glass.flow()));
+        }
+    }
+
+    fn step(&mut self, rng: &mut impl rand::Rng) -> usize {
+        if let Some(history) = &mut self.history {
+            match history.last_mut() {
+                Some(GlassOp::Advance(steps)) => *steps += 1,
+                _ => history.push(GlassOp::Advance(1))
+            }
+        }
+        if !self.check_invariants {
+            return self.glass.advance(rng);
+        }
+
+        let grains = self.glass.total_sand();
+        let moves = self.glass.advance(rng);
+        if self.violation.is_none() && let Err(violation) = self.glass.check_invariants(grains) {
+            self.violation = Some(dump_glass(&self.glass, violation));
+        }
+        moves
+    }
+
+    /// Fires the events that are due since the last `measure`, and moves on to the next phase once
+    /// the sand of the current one is done.
+    pub fn fire_events(&mut self, repeat: bool, actions: &mut EventActions) -> Result<(), Box<dyn std::error::Error>> {
+        let mut events = Vec::<TimerEvent>::new();
+
+        self.warnings.poll(self.timer.range.duration - self.elapsed, &mut events);
+        self.hooks.poll(self.timer.range.duration - self.elapsed, &mut events);
+
+        if self.time_progress() >= 1.0 && !self.completed {
+            self.completed = true;
+            events.push(TimerEvent::Completed);
+        }
+
+        for event in events {
+            actions.handle(event, self.phase())?;
+        }
+
+        let round_end = self.timer.range.start + self.timer.range.duration;
+        let next_phase = if self.phase_index + 1 < self.phases.len() {
+            Some((self.phase_index + 1, round_end))
+        } else if repeat {
+            Some((0, round_end))
+        } else if let Some(recurrence) = &self.recurrence {
+            // The first start is the one of the first phase, not whenever it was resumed from pausing
+            let first_start = self.timer.range.start - self.phases[..self.phase_index].iter().map(|phase| phase.duration).sum::<TimeDelta>();
+            recurrence.next(first_start, round_end).map(|start| (0, start))
+        } else {
+            None
+        };
+
+        let sand_done = self.completed && (!self.simulate || (self.glass.count_top_sand() == 0 && self.inactive_steps >= Hourglass::SETTLED_AFTER_STEPS));
+        if sand_done {
+            match next_phase {
+                Some((next_phase, start)) => {
+                    // The next phase starts when the last one was supposed to end, not when the sand got done
+                    self.flip_glass();
+                    self.timer = Timer::new(TimeRange {
+                        start,
+                        duration: self.phases[next_phase].duration
+                    });
+                    self.phase_index = next_phase;
+                    self.completed = false;
+                    self.inactive_steps = 0;
+                    self.warnings.reset(self.timer.range.duration);
+                    self.hooks.reset(self.timer.range.duration, self.timer.range.duration);
+                    actions.handle(TimerEvent::Flipped, self.phase())?;
+                    actions.log(LogEvent::started(self.timer.range.duration), self.phase())?;
+                },
+                None => self.finished = true
+            }
+        }
+
+        Ok(())
+    }
+
+}
+
+
+/// How a session went over the whole run.
+#[derive(Default, Clone, Debug)]
+pub struct RunStats {
+    pub steps: u64,
+    /// Grain moves, over all the steps
+    pub moves: u64,
+    /// Of the sand progress from the time progress, over every `measure`
+    pub max_deviation: f64,
+    deviation_sum: f64,
+    measures: u64
+}
+
+impl RunStats {
+
+    fn add_deviation(&mut self, deviation: f64) {
+        self.max_deviation = self.max_deviation.max(deviation);
+        self.deviation_sum += deviation;
+        self.measures += 1;
+    }
+
+    pub fn mean_deviation(&self) -> f64 {
+        if self.measures != 0 {
+            self.deviation_sum / self.measures as f64
+        } else {
+            0.0
+        }
+    }
+
+}
+
+
+/// Describes `violation`, with the grains in every cell, the walls, and the moves of the step that
+/// caused it.
+fn dump_glass(glass: &Hourglass, violation: InvariantViolation) -> String {
+    let mut dump = format!("sand went wrong in step {}: {}\n", glass.steps(), violation);
+
+    // Counts of 10 and up are letters, like in hexadecimal. Sand where it can't be is a `!`.
+    for (y, row) in glass.rows().enumerate() {
+        for (x, cell) in row.enumerate() {
+            let inside = glass.interior_positions().contains(&(x, y));
+            dump.push(match cell {
+                CellView::Wall(_) if glass.sand()[(x, y)] > 0 => '!',
+                CellView::Wall(ch) => ch,
+                CellView::Sand(0) if !inside => ' ',
+                CellView::Sand(_) if !inside => '!',
+                CellView::Sand(0) => '.',
+                CellView::Sand(grains) => char::from_digit(grains as u32, 36).unwrap_or('?')
+            });
+        }
+        dump.push('\n');
+    }
+
+    dump += &format!("{} grains, up to {} per cell, {} moves in the last step:", glass.total_sand(), glass.cell_capacity(), glass.last_moves().len());
+    for (from, to) in glass.last_moves() {
+        dump += &format!(" {:?}->{:?}", from, to);
+    }
+    if glass.last_moves().is_empty() {
+        dump += " none";
+    }
+    dump
+}
+
+/// Says so on stderr when sand that was moved into place didn't come to rest. The glass keeps
+/// going with it still moving, which is no reason to stop the timer.
+fn report_unsettled(settled: SettleReport) {
+    if !settled.converged {
+        eprintln!("the sand didn't come to rest in {} steps, carrying on with it still moving", settled.steps);
+    }
+}
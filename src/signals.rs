@@ -0,0 +1,30 @@
+use signal_hook::consts::{SIGINT, SIGTERM, SIGUSR1, SIGUSR2};
+
+use crate::control::Command;
+
+
+/// Turns the signals we handle into commands, so the main loop can react to them between frames.
+///
+/// SIGUSR1 toggles pause, SIGUSR2 flips the glass, and SIGINT and SIGTERM quit.
+pub struct Signals {
+    inner: signal_hook::iterator::Signals
+}
+
+impl Signals {
+
+    pub fn new() -> std::io::Result<Signals> {
+        Ok(Signals {
+            inner: signal_hook::iterator::Signals::new([SIGUSR1, SIGUSR2, SIGINT, SIGTERM])?
+        })
+    }
+
+    /// Commands from signals received since the last call, without blocking.
+    pub fn pending(&mut self) -> impl Iterator<Item = Command> + '_ {
+        self.inner.pending().map(|signal| match signal {
+            SIGUSR1 => Command::TogglePause,
+            SIGUSR2 => Command::Flip,
+            _ => Command::Quit { exit_code: 128 + signal as u8 }
+        })
+    }
+
+}
@@ -0,0 +1,54 @@
+use std::path::Path;
+
+use chrono::{DateTime, TimeDelta, Utc};
+use hourgals::Hourglass;
+
+use crate::timer::{TimeRange, Timer};
+
+
+/// What gets written into a state file. `G` is a reference when saving and owned when loading.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SavedState<G> {
+    start: DateTime<Utc>,
+    duration_millis: i64,
+    paused_at: Option<DateTime<Utc>>,
+    glass: G
+}
+
+
+/// Writes the state to `path`, creating its directory if needed. The file is replaced in one go, so
+/// it's never left half written.
+pub fn save(path: &Path, timer: &Timer, glass: &Hourglass) -> std::io::Result<()> {
+    let state = SavedState {
+        start: timer.range.start,
+        duration_millis: timer.range.duration.num_milliseconds(),
+        paused_at: timer.paused_at,
+        glass
+    };
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    let mut temp_name = path.as_os_str().to_owned();
+    temp_name.push(".tmp");
+
+    std::fs::write(&temp_name, serde_json::to_vec(&state)?)?;
+    std::fs::rename(&temp_name, path)
+}
+
+/// Reads a state written by `save`.
+pub fn load(path: &Path) -> Result<(Timer, Hourglass), Box<dyn std::error::Error>> {
+    let state: SavedState<Hourglass> = serde_json::from_slice(&std::fs::read(path)?)?;
+
+    Ok((
+        Timer {
+            range: TimeRange {
+                start: state.start,
+                duration: TimeDelta::milliseconds(state.duration_millis)
+            },
+            paused_at: state.paused_at
+        },
+        state.glass
+    ))
+}
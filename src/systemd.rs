@@ -0,0 +1,79 @@
+use std::os::unix::net::UnixDatagram;
+use std::time::{Duration, Instant};
+
+use crate::output::Progress;
+
+
+/// Tells systemd how the timer is doing, when it's run as a service with `Type=notify`: that it's
+/// ready once it's set up, the time left for `systemctl status`, and that it's still alive if the
+/// service has `WatchdogSec=`.
+pub struct SystemdNotifier {
+    socket: UnixDatagram,
+    /// How often the watchdog wants to hear from us
+    watchdog_interval: Option<Duration>,
+    last_ping: Option<Instant>,
+    status: String
+}
+
+impl SystemdNotifier {
+
+    /// Connects to the socket in `NOTIFY_SOCKET`. None if there isn't one, when it's not run by
+    /// systemd.
+    pub fn from_env() -> std::io::Result<Option<SystemdNotifier>> {
+        let Some(path) = std::env::var_os("NOTIFY_SOCKET") else {
+            return Ok(None);
+        };
+
+        let socket = UnixDatagram::unbound()?;
+        match path.to_str().and_then(|path| path.strip_prefix('@')) {
+            #[cfg(target_os = "linux")]
+            Some(name) => {
+                use std::os::linux::net::SocketAddrExt;
+                socket.connect_addr(&std::os::unix::net::SocketAddr::from_abstract_name(name)?)?;
+            },
+            _ => socket.connect(&path)?
+        }
+
+        // The pid is there when the watchdog is meant for another process, like a shell wrapping us
+        let for_us = std::env::var("WATCHDOG_PID").ok().is_none_or(|pid| pid == std::process::id().to_string());
+        let watchdog_interval = std::env::var("WATCHDOG_USEC").ok()
+            .and_then(|usec| usec.parse::<u64>().ok())
+            .filter(|&usec| usec > 0 && for_us)
+            .map(Duration::from_micros);
+
+        Ok(Some(SystemdNotifier { socket, watchdog_interval, last_ping: None, status: String::new() }))
+    }
+
+    fn send(&self, message: &str) -> std::io::Result<()> {
+        self.socket.send(message.as_bytes()).map(|_| ())
+    }
+
+    pub fn ready(&self) -> std::io::Result<()> {
+        self.send("READY=1")
+    }
+
+    /// Updates the status if it changed, and pings the watchdog twice as often as it asks for.
+    pub fn update(&mut self, progresses: &[&Progress]) -> std::io::Result<()> {
+        let status = crate::output::plain_status(progresses);
+        if status != self.status {
+            self.send(&format!("STATUS={}", status))?;
+            self.status = status;
+        }
+
+        if let Some(interval) = self.watchdog_interval && self.last_ping.is_none_or(|time| time.elapsed() >= interval / 2) {
+            self.send("WATCHDOG=1")?;
+            self.last_ping = Some(Instant::now());
+        }
+
+        Ok(())
+    }
+
+}
+
+impl Drop for SystemdNotifier {
+
+    fn drop(&mut self) {
+        let _ = self.send("STOPPING=1");
+    }
+
+}
@@ -0,0 +1,671 @@
+use chrono::{DateTime, Datelike, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta, TimeZone, Utc, Weekday};
+
+
+/// The time zone that times without an offset are read in.
+#[derive(Clone, Copy, Debug)]
+pub enum Zone {
+    Local,
+    Named(chrono_tz::Tz)
+}
+
+impl Zone {
+
+    fn today(&self, now: DateTime<Utc>) -> NaiveDate {
+        self.local_time(now).date()
+    }
+
+    /// What a wall clock in this zone shows at `time`.
+    pub fn local_time(&self, time: DateTime<Utc>) -> NaiveDateTime {
+        match self {
+            Zone::Local => time.with_timezone(&chrono::Local).naive_local(),
+            Zone::Named(tz) => time.with_timezone(tz).naive_local()
+        }
+    }
+
+    /// Like `17:45`, with the date in front if it's not today.
+    pub fn format_time_of_day(&self, time: DateTime<Utc>) -> String {
+        let local = self.local_time(time);
+
+        if local.date() == self.today(Utc::now()) {
+            local.format("%H:%M").to_string()
+        } else {
+            local.format("%Y-%m-%d %H:%M").to_string()
+        }
+    }
+
+    /// The instant a wall clock in this zone shows `time`. When the clocks go back and it shows
+    /// it twice, the first one is picked.
+    pub fn resolve(&self, time: NaiveDateTime) -> Result<DateTime<Utc>, String> {
+        let resolved = match self {
+            Zone::Local => chrono::Local.from_local_datetime(&time).earliest().map(|time| time.to_utc()),
+            Zone::Named(tz) => tz.from_local_datetime(&time).earliest().map(|time| time.to_utc())
+        };
+
+        resolved.ok_or_else(|| format!("{} doesn't exist in time zone {}, the clocks skip over it", time, self))
+    }
+
+}
+
+impl std::str::FromStr for Zone {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Zone, String> {
+        if name.eq_ignore_ascii_case("local") {
+            Ok(Zone::Local)
+        } else {
+            name.parse().map(Zone::Named).map_err(|_| format!("unknown time zone `{}` (use an IANA name like Europe/Budapest, or local)", name))
+        }
+    }
+}
+
+impl std::fmt::Display for Zone {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Zone::Local => write!(f, "local"),
+            Zone::Named(tz) => write!(f, "{}", tz.name())
+        }
+    }
+}
+
+
+/// A point in time from the command line.
+#[derive(Clone, Copy)]
+pub struct Timestamp {
+    pub time: DateTime<Utc>,
+    /// The same time of day tomorrow, if just a time of day was given and it was taken to be today
+    pub tomorrow: Option<DateTime<Utc>>
+}
+
+/// Reads a time of day like `14:30[:00]`, `noon` or `midnight` (today), a date and time like
+/// `2025-07-01T14:30[:00]` or `2025-07-01 14:30[:00]`, a timestamp with an offset like RFC3339, a
+/// time from now like `in 2h` or `+45m`, or a weekday with an optional time of day like `fri 17:00`
+/// (the next time it's then). Times without an offset are in `zone`.
+pub fn parse_timestamp(timestamp: &str, zone: Zone) -> Result<Timestamp, String> {
+    let timestamp = timestamp.trim();
+
+    if let Some(time) = timestamp.strip_prefix('+').or_else(|| timestamp.strip_prefix("in ")) {
+        let time = parse_time(time).map_err(|err| format!("invalid time in `{}`: {}", timestamp, err))?;
+        let time = Utc::now().checked_add_signed(time).ok_or_else(|| format!("`{}` is too far in the future", timestamp))?;
+        return Ok(Timestamp { time, tomorrow: None });
+    }
+    if timestamp == "now" {
+        return Ok(Timestamp { time: Utc::now(), tomorrow: None });
+    }
+
+    if let Ok(time) = DateTime::parse_from_rfc3339(timestamp) {
+        return Ok(Timestamp { time: time.to_utc(), tomorrow: None });
+    }
+
+    for format in ["%Y-%m-%dT%H:%M%#z", "%Y-%m-%d %H:%M%#z", "%Y-%m-%d %H:%M:%S%#z"] {
+        if let Ok(time) = DateTime::parse_from_str(timestamp, format) {
+            return Ok(Timestamp { time: time.to_utc(), tomorrow: None });
+        }
+    }
+
+    for format in ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"] {
+        if let Ok(time) = NaiveDateTime::parse_from_str(timestamp, format) {
+            return Ok(Timestamp { time: zone.resolve(time)?, tomorrow: None });
+        }
+    }
+
+    let (first_word, rest) = timestamp.split_once(' ').unwrap_or((timestamp, ""));
+    if let Ok(weekday) = first_word.parse::<Weekday>() {
+        let time_of_day = match rest.trim() {
+            "" => NaiveTime::MIN,
+            time_of_day => parse_time_of_day(time_of_day).map_err(|err| format!("invalid time of day in `{}`: {}", timestamp, err))?
+        };
+
+        // Today only if it's still ahead, otherwise a week from now at the latest
+        let now = Utc::now();
+        let today = zone.today(now);
+        for days in 0..=7 {
+            let date = today + TimeDelta::days(days);
+            if date.weekday() != weekday {
+                continue;
+            }
+
+            let time = zone.resolve(date.and_time(time_of_day))?;
+            if time > now {
+                return Ok(Timestamp { time, tomorrow: None });
+            }
+        }
+    }
+
+    let time_of_day = parse_time_of_day(timestamp).map_err(|err| format!("invalid timestamp `{}`: {}", timestamp, err))?;
+    let today = zone.today(Utc::now());
+
+    Ok(Timestamp {
+        time: zone.resolve(today.and_time(time_of_day))?,
+        tomorrow: today.succ_opt().map(|tomorrow| zone.resolve(tomorrow.and_time(time_of_day))).transpose()?
+    })
+}
+
+/// Reads `14:30[:00]`, `noon`, or `midnight`.
+fn parse_time_of_day(text: &str) -> Result<NaiveTime, chrono::ParseError> {
+    match text {
+        "noon" => Ok(NaiveTime::from_hms_opt(12, 0, 0).unwrap()),
+        "midnight" => Ok(NaiveTime::MIN),
+        _ => NaiveTime::parse_from_str(text, "%H:%M:%S").or_else(|_| NaiveTime::parse_from_str(text, "%H:%M"))
+    }
+}
+
+const VALID_UNITS: &str = "valid units are s, m, h, d, w, and y";
+
+/// Reads a duration, either as numbers with units like `1h30m`, `1.5h` or `1 hour 30 mins`, or
+/// ISO 8601 like `PT1H30M`. Errors point at the byte offset of the part that's wrong.
+pub fn parse_time(time: &str) -> Result<TimeDelta, String> {
+    let start = time.len() - time.trim_start().len();
+    let time = time.trim();
+    if time.starts_with('P') {
+        return parse_iso_duration(time, start);
+    }
+
+    if time.is_empty() {
+        return Err("empty time".to_string());
+    }
+
+    let mut seen_units = Vec::<u64>::new();
+    let mut total_nanos: u128 = 0;
+    let mut rest = time;
+    while !rest.is_empty() {
+        let offset = start + time.len() - rest.len();
+
+        let number_len = rest.find(|ch: char| !ch.is_ascii_digit() && ch != '.').unwrap_or(rest.len());
+        let (number, after_number) = rest.split_at(number_len);
+        let after_number = after_number.trim_start();
+        let unit_len = after_number.find(|ch: char| !ch.is_alphabetic()).unwrap_or(after_number.len());
+        let (unit, after_unit) = after_number.split_at(unit_len);
+
+        let token = rest[..rest.len() - after_unit.len()].trim_end();
+        if number.is_empty() {
+            return Err(format!("at byte {}: expected a number at `{}`", offset, token));
+        }
+        if unit.is_empty() {
+            return Err(format!("at byte {}: missing unit after `{}` ({})", offset, number, VALID_UNITS));
+        }
+
+        let multiplier = unit_seconds(unit).ok_or_else(|| format!("at byte {}: invalid time unit `{}` in `{}` ({})", offset, unit, token, VALID_UNITS))?;
+        if seen_units.contains(&multiplier) {
+            return Err(format!("at byte {}: `{}` repeats a unit that was already given", offset, token));
+        }
+        seen_units.push(multiplier);
+
+        total_nanos = scale_decimal(number, multiplier)
+            .map_err(|err| format!("at byte {}: the number in `{}` {}", offset, token, err))?
+            .checked_add(total_nanos)
+            .ok_or_else(|| format!("at byte {}: `{}` makes the time too long", offset, token))?;
+        rest = after_unit.trim_start().trim_start_matches(',').trim_start();
+    }
+
+    nanos_to_time(total_nanos).ok_or_else(|| format!("at byte {}: `{}` is too long", start, time))
+}
+
+/// Nanoseconds in `number` (like `2`, `1.5` or `.25`) of a unit `multiplier` seconds long. `,` also
+/// works as the decimal point, as ISO 8601 allows it.
+fn scale_decimal(number: &str, multiplier: u64) -> Result<u128, &'static str> {
+    let (whole, fraction) = number.split_once(['.', ',']).unwrap_or((number, ""));
+    let is_digits = |text: &str| text.chars().all(|ch| ch.is_ascii_digit());
+    if (whole.is_empty() && fraction.is_empty()) || !is_digits(whole) || !is_digits(fraction) {
+        return Err("is malformed");
+    }
+
+    const TOO_BIG: &str = "is too big";
+    let unit_nanos = multiplier as u128 * 1_000_000_000;
+    let whole: u128 = if whole.is_empty() { 0 } else { whole.parse().map_err(|_| TOO_BIG)? };
+
+    // Digits past the nanoseconds of a year don't matter
+    let fraction = &fraction[..fraction.len().min(18)];
+    let fraction_nanos = if fraction.is_empty() {
+        0
+    } else {
+        fraction.parse::<u128>().map_err(|_| TOO_BIG)? * unit_nanos / 10u128.pow(fraction.len() as u32)
+    };
+
+    whole.checked_mul(unit_nanos).and_then(|nanos| nanos.checked_add(fraction_nanos)).ok_or(TOO_BIG)
+}
+
+/// `None` if it doesn't fit in a `TimeDelta`.
+fn nanos_to_time(nanos: u128) -> Option<TimeDelta> {
+    TimeDelta::new((nanos / 1_000_000_000).try_into().ok()?, (nanos % 1_000_000_000) as u32)
+}
+
+/// Seconds in a unit, by its symbol or (singular or plural) name.
+fn unit_seconds(unit: &str) -> Option<u64> {
+    Some(match unit {
+        "s" | "sec" | "secs" | "second" | "seconds" => 1,
+        "m" | "min" | "mins" | "minute" | "minutes" => 60,
+        "h" | "hr" | "hrs" | "hour" | "hours" => 60 * 60,
+        "d" | "day" | "days" => 60 * 60 * 24,
+        "w" | "wk" | "wks" | "week" | "weeks" => 60 * 60 * 24 * 7,
+        "y" | "yr" | "yrs" | "year" | "years" => 60 * 60 * 24 * 365,
+        _ => return None
+    })
+}
+
+/// Reads an ISO 8601 duration like `P1DT2H30M`, which starts `start` bytes into the text. Months are
+/// rejected, since they don't have a fixed length. Weeks are 7 days, and years 365.
+fn parse_iso_duration(time: &str, start: usize) -> Result<TimeDelta, String> {
+    let Some(rest) = time.strip_prefix('P') else {
+        return Err(format!("at byte {}: ISO 8601 duration `{}` must start with P", start, time));
+    };
+    if rest.is_empty() || rest == "T" {
+        return Err(format!("at byte {}: ISO 8601 duration `{}` has no components", start, time));
+    }
+
+    let mut seen_designators = Vec::<(bool, char)>::new();
+    let mut total_nanos: u128 = 0;
+    let mut in_time = false;
+    let mut number = String::new();
+    let mut number_offset = 0;
+    for (i, ch) in rest.char_indices() {
+        // Past the P
+        let offset = start + 1 + i;
+
+        if ch.is_ascii_digit() || ch == '.' || ch == ',' {
+            if number.is_empty() {
+                number_offset = offset;
+            }
+            number.push(ch);
+            continue;
+        }
+
+        if ch == 'T' && !in_time && number.is_empty() {
+            in_time = true;
+            continue;
+        }
+
+        if number.is_empty() {
+            return Err(format!("at byte {}: expected a number before `{}` in ISO 8601 duration", offset, ch));
+        }
+
+        let multiplier: u64 = match (in_time, ch) {
+            (false, 'Y') => 60 * 60 * 24 * 365,
+            (false, 'M') => return Err(format!("at byte {}: months in ISO 8601 durations aren't supported, since their length varies", number_offset)),
+            (false, 'W') => 60 * 60 * 24 * 7,
+            (false, 'D') => 60 * 60 * 24,
+            (true, 'H') => 60 * 60,
+            (true, 'M') => 60,
+            (true, 'S') => 1,
+            _ => return Err(format!("at byte {}: unexpected `{}{}` in ISO 8601 duration", number_offset, number, ch))
+        };
+
+        if seen_designators.contains(&(in_time, ch)) {
+            return Err(format!("at byte {}: `{}{}` repeats a designator that was already given", number_offset, number, ch));
+        }
+        seen_designators.push((in_time, ch));
+
+        total_nanos = scale_decimal(&number, multiplier)
+            .map_err(|err| format!("at byte {}: the number `{}` in ISO 8601 duration {}", number_offset, number, err))?
+            .checked_add(total_nanos)
+            .ok_or_else(|| format!("at byte {}: `{}{}` makes the time too long", number_offset, number, ch))?;
+        number.clear();
+    }
+
+    if !number.is_empty() {
+        return Err(format!("at byte {}: missing designator after `{}` in ISO 8601 duration", number_offset, number));
+    }
+
+    nanos_to_time(total_nanos).ok_or_else(|| format!("at byte {}: `{}` is too long", start, time))
+}
+
+/// Formats a duration the way `parse_time` reads it, for example 1h30m. Fractions of seconds are dropped.
+pub fn format_time(time: TimeDelta) -> String {
+    let mut seconds = time.num_seconds().max(0);
+    if seconds == 0 {
+        return "0s".to_string();
+    }
+
+    let mut text = String::new();
+    for (unit, multiplier) in [('y', 60 * 60 * 24 * 365), ('d', 60 * 60 * 24), ('h', 60 * 60), ('m', 60), ('s', 1)] {
+        if seconds >= multiplier {
+            text += &format!("{}{}", seconds / multiplier, unit);
+            seconds %= multiplier;
+        }
+    }
+
+    text
+}
+
+
+pub struct TimeRange {
+    pub start: DateTime<Utc>,
+    pub duration: TimeDelta
+}
+
+impl TimeRange {
+
+    /// Works out the range from the options given. An `end` without a date that's not after `begin`
+    /// (or now, without `begin`) is taken to be tomorrow, any other `end` has to be after them.
+    pub fn try_from_args(begin: Option<Timestamp>, end: Option<Timestamp>, length: Option<TimeDelta>) -> Result<TimeRange, &'static str> {
+        let now = Utc::now();
+
+        let begin = begin.map(|begin| begin.time);
+        let end = end.map(|end| match end.tomorrow {
+            Some(tomorrow) if end.time <= begin.unwrap_or(now) => tomorrow,
+            _ => end.time
+        });
+
+        match (&begin, &end, &length) {
+            (None, None, None) => Err("must define time range with some combination of `begin`, `end`, and `length`"),
+            (None, None, Some(length)) => Ok(TimeRange {
+                start: now,
+                duration: *length
+            }),
+            (None, Some(end), None) if *end <= now => Err("`end` must be in the future"),
+            (None, Some(end), None) => Ok(TimeRange {
+                start: now,
+                duration: *end - now
+            }),
+            (None, Some(end), Some(length)) => Ok(TimeRange {
+                start: *end - *length,
+                duration: *length
+            }),
+            (Some(_), None, None) => Err("must provide duration with `end` or `length`"),
+            (Some(begin), None, Some(length)) => Ok(TimeRange {
+                start: *begin,
+                duration: *length
+            }),
+            (Some(begin), Some(end), _) if *end <= *begin => Err("`end` must be after `begin`"),
+            (Some(begin), Some(end), None) => Ok(TimeRange {
+                start: *begin,
+                duration: *end - *begin
+            }),
+            (Some(begin), Some(end), Some(length)) => if (*end - *begin) == *length {
+                Ok(TimeRange {
+                    start: *begin,
+                    duration: *length
+                })
+            } else {
+                Err("`length` and `begin`..`end` must define the same duration")
+            },
+        }
+    }
+
+}
+
+
+/// A time range that can be paused and turned over while it runs.
+pub struct Timer {
+    pub range: TimeRange,
+    pub paused_at: Option<DateTime<Utc>>
+}
+
+impl Timer {
+
+    pub fn new(range: TimeRange) -> Timer {
+        Timer {
+            range,
+            paused_at: None
+        }
+    }
+
+    /// Time passed since the start, not counting the current pause.
+    pub fn elapsed(&self, now: DateTime<Utc>) -> TimeDelta {
+        self.paused_at.unwrap_or(now) - self.range.start
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused_at.is_some()
+    }
+
+    pub fn pause(&mut self, now: DateTime<Utc>) {
+        if self.paused_at.is_none() {
+            self.paused_at = Some(now);
+        }
+    }
+
+    /// Continues the timer, pushing the range back by the length of the pause.
+    pub fn resume(&mut self, now: DateTime<Utc>) {
+        if let Some(paused_at) = self.paused_at.take() {
+            self.range.start += now - paused_at;
+        }
+    }
+
+    pub fn toggle_pause(&mut self, now: DateTime<Utc>) {
+        if self.is_paused() {
+            self.resume(now);
+        } else {
+            self.pause(now);
+        }
+    }
+
+    /// Swaps the elapsed and remaining time, like the sand does when the glass is turned over.
+    pub fn flip(&mut self, now: DateTime<Utc>) {
+        let elapsed = self.elapsed(now).clamp(TimeDelta::zero(), self.range.duration);
+        self.range.start = self.paused_at.unwrap_or(now) - (self.range.duration - elapsed);
+    }
+
+    /// Moves the range and the pause later by `by`, as if the time in between didn't happen.
+    pub fn shift(&mut self, by: TimeDelta) {
+        self.range.start += by;
+        if let Some(paused_at) = &mut self.paused_at {
+            *paused_at += by;
+        }
+    }
+
+    /// Makes the range longer. Works whether or not the time is already up.
+    pub fn add(&mut self, time: TimeDelta) {
+        self.range.duration += time;
+    }
+
+}
+
+
+/// Notices when the wall clock jumps compared to the monotonic clock, as it does when the system
+/// wakes up from suspend or the clock gets set.
+pub struct ClockWatch {
+    wall: DateTime<Utc>,
+    monotonic: std::time::Instant
+}
+
+impl ClockWatch {
+
+    /// Differences smaller than this are just the clocks being read at slightly different times
+    const TOLERANCE: TimeDelta = TimeDelta::seconds(2);
+
+    pub fn new(now: DateTime<Utc>) -> ClockWatch {
+        ClockWatch { wall: now, monotonic: std::time::Instant::now() }
+    }
+
+    /// How far the wall clock jumped since the last check, if it did. Returns the wall clock time
+    /// that it jumped from, and the size of the jump.
+    pub fn check(&mut self, now: DateTime<Utc>) -> Option<(DateTime<Utc>, TimeDelta)> {
+        let monotonic = std::time::Instant::now();
+        let expected = TimeDelta::from_std(monotonic - self.monotonic).unwrap_or_default();
+        let before = self.wall + expected;
+
+        self.wall = now;
+        self.monotonic = monotonic;
+
+        let jump = now - before;
+        (jump.abs() > Self::TOLERANCE).then_some((before, jump))
+    }
+
+}
+
+
+/// Makes time pass `scale` times as fast from `origin` on, for previewing timers quickly (or slowly).
+pub struct ScaledClock {
+    origin: DateTime<Utc>,
+    scale: f64
+}
+
+impl ScaledClock {
+
+    pub fn new(origin: DateTime<Utc>, scale: f64) -> ScaledClock {
+        ScaledClock { origin, scale }
+    }
+
+    /// What the time is by this clock when the wall clock says `wall`.
+    pub fn at(&self, wall: DateTime<Utc>) -> DateTime<Utc> {
+        match self.scale {
+            1.0 => wall,
+            scale => self.origin + TimeDelta::milliseconds(((wall - self.origin).num_milliseconds() as f64 * scale) as i64)
+        }
+    }
+
+}
+
+/// Reads a speed like `4x`, `0.5x`, or just `2`.
+pub fn parse_speed(text: &str) -> Result<f64, String> {
+    match text.strip_suffix('x').unwrap_or(text).parse::<f64>() {
+        Ok(speed) if speed > 0.0 && speed.is_finite() => Ok(speed),
+        _ => Err(format!("invalid speed `{}` (use something like 4x)", text))
+    }
+}
+
+
+/// One of the timers run back to back.
+pub struct Phase {
+    pub label: Option<String>,
+    pub duration: TimeDelta
+}
+
+/// Reads a time like `parse_time`, optionally with a label in front, as in `tea:3m`.
+pub fn parse_labeled_time(text: &str) -> Result<Phase, String> {
+    let (label, time) = match text.rsplit_once(':') {
+        Some((label, time)) => (Some(label.to_string()), time),
+        None => (None, text)
+    };
+
+    match parse_time(time) {
+        Ok(duration) => Ok(Phase { label, duration }),
+        Err(err) => Err(format!("invalid time `{}`: {}", time, err))
+    }
+}
+
+/// Reads a schedule of phases, one per line, like `warmup 5m` or `interval 1m x8`. The label comes
+/// before the time and is optional, and `xN` at the end repeats the phase N times. Empty lines and
+/// ones starting with `#` are skipped.
+pub fn parse_schedule(text: &str) -> Result<Vec<Phase>, String> {
+    let mut phases = Vec::<Phase>::new();
+
+    for (line_index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut words: Vec<&str> = line.split_whitespace().collect();
+        let mut repeat = 1;
+        if let Some(count) = words.last().and_then(|word| word.strip_prefix('x')) && let Ok(count) = count.parse::<u32>() {
+            if count == 0 {
+                return Err(format!("line {}: can't repeat 0 times", line_index + 1));
+            }
+            repeat = count;
+            words.pop();
+        }
+
+        // The time is the longest run of words at the end that reads as one, since it may have spaces in it
+        let Some((label, duration)) = (0..words.len()).find_map(|split| {
+            let (label, time) = words.split_at(split);
+            parse_time(&time.join(" ")).ok().map(|duration| (label.join(" "), duration))
+        }) else {
+            return Err(format!("line {}: no time in `{}`", line_index + 1, line));
+        };
+
+        for round in 1..=repeat {
+            let label = match (label.is_empty(), repeat) {
+                (true, 1) => None,
+                (true, _) => Some(format!("{}/{}", round, repeat)),
+                (false, 1) => Some(label.clone()),
+                (false, _) => Some(format!("{} {}/{}", label, round, repeat))
+            };
+            phases.push(Phase { label, duration });
+        }
+    }
+
+    if phases.is_empty() {
+        return Err("the schedule is empty".to_string());
+    }
+
+    Ok(phases)
+}
+
+/// Work phases with short breaks between them, and a long break after the last one.
+pub fn pomodoro_phases(work: TimeDelta, short_break: TimeDelta, long_break: TimeDelta, cycles: u32) -> Vec<Phase> {
+    let mut phases = Vec::<Phase>::new();
+
+    for cycle in 1..=cycles {
+        phases.push(Phase {
+            label: Some(format!("work {}/{}", cycle, cycles)),
+            duration: work
+        });
+        phases.push(if cycle < cycles {
+            Phase { label: Some("break".to_string()), duration: short_break }
+        } else {
+            Phase { label: Some("long break".to_string()), duration: long_break }
+        });
+    }
+
+    phases
+}
+
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeDelta;
+
+    use super::parse_time;
+
+    #[test]
+    fn units_add_up() {
+        assert_eq!(parse_time("90s"), Ok(TimeDelta::seconds(90)));
+        assert_eq!(parse_time("1h30m"), Ok(TimeDelta::minutes(90)));
+        assert_eq!(parse_time(" 1 hour, 30 mins "), Ok(TimeDelta::minutes(90)));
+        assert_eq!(parse_time("2d 1w"), Ok(TimeDelta::days(9)));
+        assert_eq!(parse_time("1y"), Ok(TimeDelta::days(365)));
+    }
+
+    #[test]
+    fn fractions() {
+        assert_eq!(parse_time("1.5h"), Ok(TimeDelta::minutes(90)));
+        assert_eq!(parse_time(".25m"), Ok(TimeDelta::seconds(15)));
+        assert_eq!(parse_time("0.001s"), Ok(TimeDelta::milliseconds(1)));
+        assert_eq!(parse_time("1.m"), Ok(TimeDelta::minutes(1)));
+        assert_eq!(parse_time("1.2.3s"), Err("at byte 0: the number in `1.2.3s` is malformed".to_string()));
+        assert_eq!(parse_time(".s"), Err("at byte 0: the number in `.s` is malformed".to_string()));
+    }
+
+    #[test]
+    fn errors_point_at_the_wrong_part() {
+        assert_eq!(parse_time(""), Err("empty time".to_string()));
+        assert_eq!(parse_time("5"), Err("at byte 0: missing unit after `5` (valid units are s, m, h, d, w, and y)".to_string()));
+        assert_eq!(parse_time("1h 30x"), Err("at byte 3: invalid time unit `x` in `30x` (valid units are s, m, h, d, w, and y)".to_string()));
+        assert_eq!(parse_time("  1h m"), Err("at byte 5: expected a number at `m`".to_string()));
+    }
+
+    #[test]
+    fn repeated_units_are_rejected() {
+        assert_eq!(parse_time("1m 2m"), Err("at byte 3: `2m` repeats a unit that was already given".to_string()));
+        // The same unit by another name
+        assert_eq!(parse_time("1h2hours"), Err("at byte 2: `2hours` repeats a unit that was already given".to_string()));
+    }
+
+    #[test]
+    fn too_long() {
+        assert_eq!(parse_time("99999999999999999999999999999999999999999s"), Err("at byte 0: the number in `99999999999999999999999999999999999999999s` is too big".to_string()));
+        assert_eq!(parse_time("300000000y"), Err("at byte 0: `300000000y` is too long".to_string()));
+    }
+
+    #[test]
+    fn iso_8601() {
+        assert_eq!(parse_time("PT1H30M"), Ok(TimeDelta::minutes(90)));
+        assert_eq!(parse_time("P1DT12H"), Ok(TimeDelta::hours(36)));
+        assert_eq!(parse_time("P2W"), Ok(TimeDelta::days(14)));
+        assert_eq!(parse_time("PT0,5S"), Ok(TimeDelta::milliseconds(500)));
+        assert_eq!(parse_time("PT1.5M"), Ok(TimeDelta::seconds(90)));
+    }
+
+    #[test]
+    fn iso_8601_errors() {
+        assert_eq!(parse_time("P1M"), Err("at byte 1: months in ISO 8601 durations aren't supported, since their length varies".to_string()));
+        assert_eq!(parse_time("P"), Err("at byte 0: ISO 8601 duration `P` has no components".to_string()));
+        assert_eq!(parse_time("PT"), Err("at byte 0: ISO 8601 duration `PT` has no components".to_string()));
+        assert_eq!(parse_time(" PT5"), Err("at byte 3: missing designator after `5` in ISO 8601 duration".to_string()));
+        assert_eq!(parse_time("PTH"), Err("at byte 2: expected a number before `H` in ISO 8601 duration".to_string()));
+        assert_eq!(parse_time("P1H"), Err("at byte 1: unexpected `1H` in ISO 8601 duration".to_string()));
+        assert_eq!(parse_time("PT1M2M"), Err("at byte 4: `2M` repeats a designator that was already given".to_string()));
+    }
+
+}
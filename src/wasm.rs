@@ -0,0 +1,136 @@
+//! Bindings for running the simulation from JavaScript, built with `--features wasm` for
+//! `wasm32-unknown-unknown`.
+
+use rand::SeedableRng;
+use wasm_bindgen::prelude::*;
+
+use crate::{Hourglass, LayoutCell};
+
+
+/// An hourglass together with the RNG that drives it. JavaScript has no way to pass an RNG in, so
+/// it's seeded once up front.
+#[wasm_bindgen(js_name = Hourglass)]
+pub struct WasmHourglass {
+    glass: Hourglass,
+    rng: rand::rngs::SmallRng
+}
+
+#[wasm_bindgen(js_class = Hourglass)]
+impl WasmHourglass {
+
+    /// An empty, unpinched glass. Throws if the dimensions aren't valid, like `Hourglass::try_new`.
+    #[wasm_bindgen(constructor)]
+    pub fn new(width: usize, height: usize, seed: u32) -> Result<WasmHourglass, JsError> {
+        Ok(WasmHourglass {
+            glass: Hourglass::try_new(width, height)?,
+            rng: rand::rngs::SmallRng::seed_from_u64(seed as u64)
+        })
+    }
+
+    /// Like `new`, with cells that hold `cellCapacity` grains instead of 2.
+    #[wasm_bindgen(js_name = withCellCapacity)]
+    pub fn with_cell_capacity(width: usize, height: usize, cell_capacity: u8, seed: u32) -> Result<WasmHourglass, JsError> {
+        Ok(WasmHourglass {
+            glass: Hourglass::try_with_cell_capacity(width, height, cell_capacity)?,
+            rng: rand::rngs::SmallRng::seed_from_u64(seed as u64)
+        })
+    }
+
+    #[wasm_bindgen(js_name = cellCapacity)]
+    pub fn cell_capacity(&self) -> u8 {
+        self.glass.cell_capacity()
+    }
+
+    pub fn width(&self) -> usize {
+        self.glass.width()
+    }
+
+    pub fn height(&self) -> usize {
+        self.glass.height()
+    }
+
+    #[wasm_bindgen(js_name = fillWithSandFromTop)]
+    pub fn fill_with_sand_from_top(&mut self, fullness: f32) {
+        self.glass.fill_with_sand_from_top(fullness);
+    }
+
+    /// Lets the sand fall into place without any of it changing halves. Returns whether it came to
+    /// rest, instead of still moving after as many steps as it gets.
+    #[wasm_bindgen(js_name = settleState)]
+    pub fn settle_state(&mut self) -> bool {
+        self.glass.settle_state(&mut self.rng).converged
+    }
+
+    pub fn pinched(&self) -> bool {
+        self.glass.pinched()
+    }
+
+    pub fn pinch(&mut self) {
+        self.glass.pinch();
+    }
+
+    pub fn unpinch(&mut self) {
+        self.glass.unpinch();
+    }
+
+    /// The chance of a grain being let through the neck, from 0 (pinched) to 1.
+    pub fn flow(&self) -> f32 {
+        self.glass.flow()
+    }
+
+    #[wasm_bindgen(js_name = setFlow)]
+    pub fn set_flow(&mut self, flow: f32) {
+        self.glass.set_flow(flow);
+    }
+
+    /// Moves sand between the halves so `progress` (0 to 1) of it is in the bottom, and lets it fall
+    /// into place. Returns whether it came to rest, like `settleState`.
+    #[wasm_bindgen(js_name = setProgress)]
+    pub fn set_progress(&mut self, progress: f32) -> bool {
+        self.glass.set_progress(progress, &mut self.rng).converged
+    }
+
+    /// Changes the size of the glass, keeping how full it is and how much of the sand is down.
+    /// Throws if the dimensions aren't valid.
+    pub fn resize(&mut self, width: usize, height: usize) -> Result<(), JsError> {
+        self.glass = self.glass.resized(width, height, &mut self.rng)?.0;
+        Ok(())
+    }
+
+    pub fn flip(&mut self) {
+        self.glass.flip();
+    }
+
+    /// Does one step of the simulation, and returns how many grains moved.
+    pub fn advance(&mut self) -> usize {
+        self.glass.advance(&mut self.rng)
+    }
+
+    /// The amount of sand in each cell, row by row from the top. Walls and the outside are 0, see
+    /// `walls` for telling them apart.
+    pub fn cells(&self) -> Vec<u8> {
+        self.glass.sand().iter().copied().collect()
+    }
+
+    /// 1 for each solid cell and 0 for the rest, in the same order as `cells`.
+    pub fn walls(&self) -> Vec<u8> {
+        self.glass.layout().iter().map(|cell| matches!(cell, LayoutCell::Wall(_) | LayoutCell::Frame(_)) as u8).collect()
+    }
+
+    #[wasm_bindgen(js_name = countTopSand)]
+    pub fn count_top_sand(&self) -> usize {
+        self.glass.count_top_sand()
+    }
+
+    #[wasm_bindgen(js_name = countBottomSand)]
+    pub fn count_bottom_sand(&self) -> usize {
+        self.glass.count_bottom_sand()
+    }
+
+    /// The glass drawn as text, like `Display` does.
+    #[wasm_bindgen(js_name = toString)]
+    pub fn to_text(&self) -> String {
+        self.glass.to_string()
+    }
+
+}
@@ -0,0 +1,38 @@
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+
+
+/// Notices when a file gets modified, by checking its modification time between frames. That works
+/// the same everywhere, and a frame apart is soon enough.
+pub struct FileWatch {
+    path: PathBuf,
+    modified: Option<DateTime<Utc>>
+}
+
+impl FileWatch {
+
+    pub fn new(path: &Path) -> FileWatch {
+        FileWatch { path: path.to_path_buf(), modified: modified_time(path) }
+    }
+
+    /// When the file was last modified, as of the last check. None if it doesn't exist.
+    pub fn modified(&self) -> Option<DateTime<Utc>> {
+        self.modified
+    }
+
+    /// The new modification time, if the file was modified since the last check. Removing the file
+    /// doesn't count, but creating it does.
+    pub fn check(&mut self) -> Option<DateTime<Utc>> {
+        let modified = modified_time(&self.path);
+        let changed = modified.is_some() && modified != self.modified;
+        self.modified = modified.or(self.modified);
+
+        changed.then_some(modified).flatten()
+    }
+
+}
+
+fn modified_time(path: &Path) -> Option<DateTime<Utc>> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok().map(DateTime::<Utc>::from)
+}
@@ -0,0 +1,149 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+
+use hourgals::Hourglass;
+
+use crate::output::Progress;
+
+
+/// The page that shows the glasses, drawn from the frames coming in on `/events`.
+const PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>hourgals</title>
+<style>
+    body { background: #111; color: #eee; font-family: monospace; display: flex; flex-wrap: wrap; justify-content: center; align-items: center; gap: 4em; min-height: 100vh; margin: 0; }
+    .timer { text-align: center; }
+    pre { font-size: 3vmin; line-height: 1.1; margin: 0.5em 0; }
+    .time { font-size: 6vmin; }
+    .paused { color: #fc3; }
+    .done { color: #f44; }
+</style>
+</head>
+<body>
+<script>
+function clock(secs) {
+    secs = Math.max(0, Math.ceil(secs));
+    const hours = Math.floor(secs / 3600), minutes = Math.floor(secs / 60) % 60, seconds = secs % 60;
+    const pad = n => String(n).padStart(2, "0");
+    return (hours > 0 ? hours + ":" + pad(minutes) : minutes) + ":" + pad(seconds);
+}
+
+new EventSource("events").onmessage = event => {
+    const timers = JSON.parse(event.data);
+    while (document.body.children.length > timers.length + 1) document.body.lastChild.remove();
+    timers.forEach(({ glass, progress }, i) => {
+        let element = document.body.children[i + 1];
+        if (!element) {
+            element = document.createElement("div");
+            element.className = "timer";
+            element.innerHTML = "<div class=label></div><pre></pre><div class=time></div>";
+            document.body.append(element);
+        }
+        element.querySelector(".label").textContent = progress.label ?? "";
+        element.querySelector("pre").textContent = glass;
+        const time = element.querySelector(".time");
+        time.textContent = clock(progress.remaining_secs ?? progress.elapsed_secs) + (progress.paused ? " (paused)" : "");
+        time.className = "time" + (progress.paused ? " paused" : progress.remaining_secs === 0 ? " done" : "");
+    });
+};
+</script>
+</body>
+</html>
+"#;
+
+
+/// Serves a page over HTTP that shows the glasses as they are in the terminal, kept up to date with
+/// a stream of server-sent events, one per frame.
+pub struct WebServer {
+    /// Each connected stream, fed the frames as they come
+    clients: Arc<Mutex<Vec<Sender<Arc<String>>>>>,
+    /// For streams that connect in between frames
+    latest: Arc<Mutex<Arc<String>>>
+}
+
+impl WebServer {
+
+    pub fn bind(address: SocketAddr) -> std::io::Result<WebServer> {
+        let listener = TcpListener::bind(address)?;
+        let clients = Arc::new(Mutex::new(Vec::new()));
+        let latest = Arc::new(Mutex::new(Arc::new("[]".to_string())));
+
+        let (served_clients, served_latest) = (Arc::clone(&clients), Arc::clone(&latest));
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let (clients, latest) = (Arc::clone(&served_clients), Arc::clone(&served_latest));
+                std::thread::spawn(move || serve(stream, &clients, &latest));
+            }
+        });
+
+        Ok(WebServer { clients, latest })
+    }
+
+    /// Sends this frame to everyone watching.
+    pub fn update(&self, frames: &[(&Hourglass, &Progress)]) -> serde_json::Result<()> {
+        let frames: Vec<WebFrame> = frames.iter().map(|(glass, progress)| WebFrame { glass: Some(glass.to_string()), progress }).collect();
+        let frame = Arc::new(serde_json::to_string(&frames)?);
+
+        *self.latest.lock().unwrap() = Arc::clone(&frame);
+        // The ones that hung up are gone
+        self.clients.lock().unwrap().retain(|client| client.send(Arc::clone(&frame)).is_ok());
+        Ok(())
+    }
+
+}
+
+
+/// What's sent about each timer every frame.
+#[derive(serde::Serialize)]
+pub struct WebFrame<'a> {
+    /// The glass drawn as text
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub glass: Option<String>,
+    pub progress: &'a Progress
+}
+
+
+/// Answers one request: the page at `/`, and the frames at `/events`, which keeps the connection
+/// open for as long as the browser does.
+fn serve(stream: TcpStream, clients: &Mutex<Vec<Sender<Arc<String>>>>, latest: &Mutex<Arc<String>>) -> std::io::Result<()> {
+    let mut reader = BufReader::new(&stream);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // The headers don't matter, but have to be read before answering
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let mut stream = &stream;
+    let path = request_line.split_whitespace().nth(1).unwrap_or("");
+    let (status, content_type, body) = match path.split('?').next() {
+        Some("/events") => {
+            let (sender, frames) = std::sync::mpsc::channel();
+            clients.lock().unwrap().push(sender);
+
+            write!(stream, "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\n\r\n")?;
+            let latest = Arc::clone(&latest.lock().unwrap());
+            for frame in std::iter::once(latest).chain(frames) {
+                write!(stream, "data: {}\n\n", frame)?;
+                stream.flush()?;
+            }
+            return Ok(());
+        },
+        Some("/") => ("200 OK", "text/html; charset=utf-8", PAGE),
+        _ => ("404 Not Found", "text/plain", "not found, try /\n")
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status, content_type, body.len(), body
+    )?;
+    stream.flush()
+}
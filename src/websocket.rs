@@ -0,0 +1,173 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+
+use hourgals::Hourglass;
+
+use crate::output::Progress;
+use crate::web::WebFrame;
+
+
+/// Streams the progress of the timers over WebSocket, one JSON text message per frame, the same as
+/// the events of `--serve`. Anything the other end sends is ignored.
+pub struct WebSocketServer {
+    clients: Arc<Mutex<Vec<Sender<Arc<String>>>>>,
+    /// Whether the messages have the glasses drawn as text too
+    with_glasses: bool
+}
+
+impl WebSocketServer {
+
+    pub fn bind(address: SocketAddr, with_glasses: bool) -> std::io::Result<WebSocketServer> {
+        let listener = TcpListener::bind(address)?;
+        let clients = Arc::new(Mutex::new(Vec::new()));
+
+        let served_clients = Arc::clone(&clients);
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let clients = Arc::clone(&served_clients);
+                std::thread::spawn(move || serve(stream, &clients));
+            }
+        });
+
+        Ok(WebSocketServer { clients, with_glasses })
+    }
+
+    /// Sends this frame to everyone connected.
+    pub fn update(&self, frames: &[(&Hourglass, &Progress)]) -> serde_json::Result<()> {
+        let mut clients = self.clients.lock().unwrap();
+        if clients.is_empty() {
+            return Ok(());
+        }
+
+        let frames: Vec<WebFrame> = frames.iter().map(|(glass, progress)| WebFrame {
+            glass: self.with_glasses.then(|| glass.to_string()),
+            progress
+        }).collect();
+        let message = Arc::new(serde_json::to_string(&frames)?);
+
+        clients.retain(|client| client.send(Arc::clone(&message)).is_ok());
+        Ok(())
+    }
+
+}
+
+
+/// Does the opening handshake, then writes the messages until the connection goes away.
+fn serve(stream: TcpStream, clients: &Mutex<Vec<Sender<Arc<String>>>>) -> std::io::Result<()> {
+    let mut reader = BufReader::new(&stream);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut key: Option<String> = None;
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        if let Some((name, value)) = header.split_once(':') && name.trim().eq_ignore_ascii_case("sec-websocket-key") {
+            key = Some(value.trim().to_string());
+        }
+        header.clear();
+    }
+
+    let mut stream = &stream;
+    let Some(key) = key else {
+        let body = "this is a WebSocket server\n";
+        write!(stream, "HTTP/1.1 426 Upgrade Required\r\nUpgrade: websocket\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body)?;
+        return stream.flush();
+    };
+
+    // Proves to the other end that we understood the handshake, as RFC 6455 says
+    let accept = base64(&sha1(format!("{}258EAFA5-E914-47DA-95CA-C5AB0DC85B11", key).as_bytes()));
+    write!(stream, "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n", accept)?;
+    stream.flush()?;
+
+    let (sender, messages) = std::sync::mpsc::channel();
+    clients.lock().unwrap().push(sender);
+
+    for message in messages {
+        write_text_frame(&mut stream, &message)?;
+    }
+    Ok(())
+}
+
+/// A whole text message in one unmasked frame, which is what servers send.
+fn write_text_frame(out: &mut impl Write, text: &str) -> std::io::Result<()> {
+    let len = text.len();
+    let mut frame = vec![0x81]; // Final frame, text
+    match len {
+        0..=125 => frame.push(len as u8),
+        126..=0xffff => {
+            frame.push(126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        },
+        _ => {
+            frame.push(127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(text.as_bytes());
+
+    out.write_all(&frame)?;
+    out.flush()
+}
+
+
+/// SHA-1, which only the handshake needs, so it's not worth a dependency.
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut state: [u32; 5] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0];
+
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&(data.len() as u64 * 8).to_be_bytes());
+
+    for block in message.chunks_exact(64) {
+        let mut words = [0u32; 80];
+        for (i, word) in block.chunks_exact(4).enumerate() {
+            words[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            words[i] = (words[i - 3] ^ words[i - 8] ^ words[i - 14] ^ words[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = state;
+        for (i, &word) in words.iter().enumerate() {
+            let (f, k) = match i {
+                0..20 => ((b & c) | (!b & d), 0x5a827999),
+                20..40 => (b ^ c ^ d, 0x6ed9eba1),
+                40..60 => ((b & c) | (b & d) | (c & d), 0x8f1bbcdc),
+                _ => (b ^ c ^ d, 0xca62c1d6)
+            };
+            let temp = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(word);
+            (e, d, c, b, a) = (d, c, b.rotate_left(30), a, temp);
+        }
+
+        for (value, add) in state.iter_mut().zip([a, b, c, d, e]) {
+            *value = value.wrapping_add(add);
+        }
+    }
+
+    let mut digest = [0u8; 20];
+    for (bytes, value) in digest.chunks_exact_mut(4).zip(state) {
+        bytes.copy_from_slice(&value.to_be_bytes());
+    }
+    digest
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut text = String::new();
+    for chunk in data.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, &byte)| bits | (byte as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            text.push(match i <= chunk.len() {
+                true => ALPHABET[(bits >> (18 - 6 * i) & 0x3f) as usize] as char,
+                false => '='
+            });
+        }
+    }
+    text
+}